@@ -0,0 +1,143 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::grammar_units;
+use crate::Language;
+
+/// Oldest language ABI version the runtime can load unless `min` is set in the `[abi]` table.
+pub const DEFAULT_MIN_ABI_VERSION: u32 = 13;
+/// Newest language ABI version the runtime can load unless `max` is set in the `[abi]` table.
+pub const DEFAULT_MAX_ABI_VERSION: u32 = 14;
+
+/// The global `[abi]` table of the languages file: the range of language ABI versions the
+/// runtime grammars are loaded into supports.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AbiRange {
+    #[serde(default = "default_min")]
+    pub min: u32,
+    #[serde(default = "default_max")]
+    pub max: u32,
+}
+
+fn default_min() -> u32 {
+    DEFAULT_MIN_ABI_VERSION
+}
+
+fn default_max() -> u32 {
+    DEFAULT_MAX_ABI_VERSION
+}
+
+impl Default for AbiRange {
+    fn default() -> Self {
+        Self {
+            min: DEFAULT_MIN_ABI_VERSION,
+            max: DEFAULT_MAX_ABI_VERSION,
+        }
+    }
+}
+
+impl AbiRange {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn contains(&self, version: u32) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+}
+
+impl fmt::Display for AbiRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} to {}", self.min, self.max)
+    }
+}
+
+/// The ABI version of one generated parser of a grammar.
+#[derive(Debug, Clone)]
+pub struct ParserAbi {
+    /// Directory of the grammar relative to its repository, empty for the grammar at the root
+    pub path: String,
+    /// `LANGUAGE_VERSION` of its `parser.c`, or `None` if the parser was not generated
+    pub version: Option<u32>,
+}
+
+/// Reads the `LANGUAGE_VERSION` that a generated `parser.c` was written for.
+pub fn parser_abi_version(parser: &Path) -> Option<u32> {
+    let source = fs::read_to_string(parser).ok()?;
+    source.lines().find_map(|line| {
+        line.strip_prefix("#define LANGUAGE_VERSION")?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// Reads the ABI version of every grammar of a repository.
+pub fn grammar_abi_versions(language: &Language, grammar_directory: &Path) -> Vec<ParserAbi> {
+    grammar_units(language, grammar_directory)
+        .into_iter()
+        .map(|unit| ParserAbi {
+            version: parser_abi_version(&grammar_directory.join(unit.src()).join("parser.c")),
+            path: unit.path,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_abi_version_of_every_grammar() {
+        let root = std::env::temp_dir().join(format!("abi-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files = [
+            (
+                "tsx/src/parser.c",
+                "#include \"tree_sitter/parser.h\"\n\n#define LANGUAGE_VERSION 14\n",
+            ),
+            ("typescript/src/parser.c", "#define LANGUAGE_VERSION   15\n"),
+            (
+                "ungenerated/src/grammar.json",
+                "{\"name\": \"ungenerated\"}",
+            ),
+            ("broken/src/parser.c", "#define LANGUAGE_VERSION fourteen\n"),
+        ];
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        let versions: Vec<(String, Option<u32>)> = grammar_abi_versions(&language, &root)
+            .into_iter()
+            .map(|parser| (parser.path, parser.version))
+            .collect();
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(
+            versions,
+            [
+                ("broken".to_string(), None),
+                ("tsx".to_string(), Some(14)),
+                ("typescript".to_string(), Some(15)),
+                ("ungenerated".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_the_abi_range() {
+        let range: AbiRange = toml::from_str("max = 15").unwrap();
+        assert_eq!(range, AbiRange { min: 13, max: 15 });
+        assert!(range.contains(13) && range.contains(15));
+        assert!(!range.contains(12) && !range.contains(16));
+        assert!(toml::from_str::<AbiRange>("maximum = 15").is_err());
+        assert_eq!(AbiRange::default().to_string(), "13 to 14");
+    }
+}
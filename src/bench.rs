@@ -0,0 +1,227 @@
+use serde_derive::Serialize;
+use std::ffi::c_void;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Once;
+use std::time::Duration;
+use std::time::Instant;
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+
+use crate::corpus::read_corpus;
+use crate::snapshot::sample_files;
+use crate::snapshot::ErrorCounts;
+
+/// Results of benchmarking a grammar, as written to the JSON report.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BenchResult {
+    /// Commit of the grammar, to compare results across grammar versions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub inputs: usize,
+    pub bytes: usize,
+    /// How often every input was parsed
+    pub iterations: usize,
+    /// Time spent parsing, in seconds
+    pub seconds: f64,
+    pub bytes_per_second: f64,
+    /// Most memory the runtime held while parsing a single input, in bytes. Only allocations of
+    /// the runtime itself are counted: external scanners built without
+    /// `TREE_SITTER_REUSE_ALLOCATOR` call `malloc` directly, so the state they allocate is missing
+    pub peak_memory: usize,
+    /// `ERROR` nodes in the trees of all inputs
+    pub errors: usize,
+    /// `MISSING` nodes in the trees of all inputs
+    pub missing: usize,
+    /// Inputs whose tree has any error nodes
+    pub inputs_with_errors: usize,
+}
+
+/// Returns the inputs of the corpus tests in `corpus_directory` that apply to the grammar named
+/// `symbol`, named after their file and test.
+pub fn corpus_inputs(
+    corpus_directory: &Path,
+    symbol: &str,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    Ok(read_corpus(corpus_directory)?
+        .into_iter()
+        .flat_map(|(file, tests)| {
+            tests
+                .into_iter()
+                .filter(|test| test.applies_to(symbol) && !test.attributes.error)
+                .map(move |test| (format!("{}: {}", file, test.name), test.input.into_bytes()))
+        })
+        .collect())
+}
+
+/// Returns every file below a directory as an input, named by its path relative to it.
+pub fn directory_inputs(directory: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    sample_files(directory)?
+        .into_iter()
+        .map(|path| {
+            let source = fs::read(directory.join(&path)).map_err(|e| {
+                format!("Failed to read {}: {}", directory.join(&path).display(), e)
+            })?;
+            Ok((path, source))
+        })
+        .collect()
+}
+
+/// Parses every input `iterations` times with a grammar loaded into the runtime, measuring the
+/// throughput and the peak memory of the runtime, and counts the error nodes of their trees.
+/// Benchmarks have to run one at a time, as the memory of the runtime is tracked globally.
+pub fn bench_grammar(
+    grammar: &tree_sitter::Language,
+    inputs: &[(String, Vec<u8>)],
+    iterations: usize,
+) -> Result<BenchResult, String> {
+    install_counting_allocator();
+    let mut parser = Parser::new();
+    parser
+        .set_language(grammar)
+        .map_err(|e| format!("Failed to load grammar: {}", e))?;
+
+    let mut result = BenchResult {
+        inputs: inputs.len(),
+        bytes: inputs.iter().map(|(_, source)| source.len()).sum(),
+        iterations,
+        ..BenchResult::default()
+    };
+    let mut elapsed = Duration::ZERO;
+    for (name, source) in inputs {
+        for iteration in 0..iterations {
+            let baseline = ALLOCATED.load(Ordering::Relaxed);
+            PEAK.store(baseline, Ordering::Relaxed);
+            let start = Instant::now();
+            let tree = parser
+                .parse(source, None)
+                .ok_or_else(|| format!("Parsing {} was cancelled", name))?;
+            elapsed += start.elapsed();
+            let peak = PEAK.load(Ordering::Relaxed).saturating_sub(baseline);
+            result.peak_memory = result.peak_memory.max(peak);
+
+            if iteration == 0 {
+                let counts = tree_error_counts(&tree);
+                result.errors += counts.errors;
+                result.missing += counts.missing;
+                if counts != ErrorCounts::default() {
+                    result.inputs_with_errors += 1;
+                }
+            }
+        }
+    }
+    result.seconds = elapsed.as_secs_f64();
+    if result.seconds > 0.0 {
+        result.bytes_per_second = (result.bytes * iterations) as f64 / result.seconds;
+    }
+    Ok(result)
+}
+
+/// Counts the error nodes of a tree.
+pub fn tree_error_counts(tree: &Tree) -> ErrorCounts {
+    let mut counts = ErrorCounts::default();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() {
+            counts.errors += 1;
+        } else if node.is_missing() {
+            counts.missing += 1;
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return counts;
+            }
+        }
+    }
+}
+
+/// Bytes currently allocated by the runtime, and the most allocated since the last reset.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Size of the header in front of every allocation of the runtime, which holds its size and
+/// keeps the allocation aligned like `malloc` does.
+const HEADER_SIZE: usize = 16;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn realloc(pointer: *mut c_void, size: usize) -> *mut c_void;
+    fn free(pointer: *mut c_void);
+}
+
+/// Makes the runtime allocate through functions that track how much memory it holds. This has to
+/// happen before the runtime allocates anything, which it never does before the first benchmark.
+fn install_counting_allocator() {
+    static INSTALL: Once = Once::new();
+    // SAFETY: the functions only free memory they allocated themselves
+    INSTALL.call_once(|| unsafe {
+        tree_sitter::set_allocator(
+            Some(counting_malloc),
+            Some(counting_calloc),
+            Some(counting_realloc),
+            Some(counting_free),
+        )
+    });
+}
+
+/// Records an allocation changing size, from nothing for new ones and to nothing for freed ones.
+fn track_allocation(previous: usize, size: usize) {
+    if size >= previous {
+        let grown = size - previous;
+        let now = ALLOCATED.fetch_add(grown, Ordering::Relaxed) + grown;
+        PEAK.fetch_max(now, Ordering::Relaxed);
+    } else {
+        ALLOCATED.fetch_sub(previous - size, Ordering::Relaxed);
+    }
+}
+
+unsafe extern "C" fn counting_malloc(size: usize) -> *mut c_void {
+    let base = malloc(size + HEADER_SIZE).cast::<usize>();
+    if base.is_null() {
+        return base.cast();
+    }
+    *base = size;
+    track_allocation(0, size);
+    base.cast::<u8>().add(HEADER_SIZE).cast()
+}
+
+unsafe extern "C" fn counting_calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(size) = count.checked_mul(size) else {
+        return std::ptr::null_mut();
+    };
+    let pointer = counting_malloc(size);
+    if !pointer.is_null() {
+        pointer.cast::<u8>().write_bytes(0, size);
+    }
+    pointer
+}
+
+unsafe extern "C" fn counting_realloc(pointer: *mut c_void, size: usize) -> *mut c_void {
+    if pointer.is_null() {
+        return counting_malloc(size);
+    }
+    let base = pointer.cast::<u8>().sub(HEADER_SIZE);
+    let previous = *base.cast::<usize>();
+    let base = realloc(base.cast(), size + HEADER_SIZE).cast::<usize>();
+    if base.is_null() {
+        return base.cast();
+    }
+    *base = size;
+    track_allocation(previous, size);
+    base.cast::<u8>().add(HEADER_SIZE).cast()
+}
+
+unsafe extern "C" fn counting_free(pointer: *mut c_void) {
+    if pointer.is_null() {
+        return;
+    }
+    let base = pointer.cast::<u8>().sub(HEADER_SIZE);
+    track_allocation(*base.cast::<usize>(), 0);
+    free(base.cast());
+}
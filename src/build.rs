@@ -0,0 +1,469 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::hashing::sha256;
+use crate::hashing::tree_sha256;
+use crate::metadata::grammar_units;
+use crate::Language;
+
+/// Directories of a grammar repository, besides the `src` directory of each of its grammars,
+/// whose contents are compiled into its WebAssembly artifact.
+const BUILD_INPUT_DIRECTORIES: [&str; 2] = ["common", "include"];
+
+/// Image used to run emscripten when compiling through Docker or Podman without the
+/// `tree-sitter` CLI.
+const EMSCRIPTEN_IMAGE: &str = "docker.io/emscripten/emsdk";
+
+/// Returns the directory WebAssembly artifacts are written to, which sits next to the grammars
+/// directory.
+pub fn wasm_directory(directory: &Path) -> PathBuf {
+    directory.join("..").join("wasm")
+}
+
+/// A toolchain able to compile grammars to WebAssembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmToolchain {
+    Emcc,
+    Docker,
+    Podman,
+    WasiSdk,
+}
+
+impl WasmToolchain {
+    /// Toolchains in the order they are preferred when none is requested.
+    pub const ALL: [WasmToolchain; 4] = [Self::Emcc, Self::Docker, Self::Podman, Self::WasiSdk];
+}
+
+impl fmt::Display for WasmToolchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Emcc => "emcc",
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+            Self::WasiSdk => "wasi-sdk",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for WasmToolchain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|toolchain| toolchain.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown WebAssembly toolchain '{}', expected one of: emcc, docker, podman, wasi-sdk",
+                    s
+                )
+            })
+    }
+}
+
+/// The toolchain selected to compile grammars, and how it is invoked.
+#[derive(Debug, Clone)]
+pub struct WasmCompiler {
+    pub toolchain: WasmToolchain,
+    /// Program compiling the grammar: `tree-sitter`, or the toolchain itself when the
+    /// `tree-sitter` CLI is not installed
+    pub program: PathBuf,
+    /// Versions of the programs involved, used to invalidate cached builds
+    pub version: String,
+    uses_tree_sitter_cli: bool,
+}
+
+/// Selects the toolchain to compile grammars to WebAssembly with: the requested one, or else the
+/// first one available. Grammars are compiled with `tree-sitter build --wasm` when the CLI is
+/// installed, except with wasi-sdk, and by invoking the toolchain directly otherwise.
+pub async fn detect_wasm_compiler(
+    requested: Option<WasmToolchain>,
+) -> Result<WasmCompiler, String> {
+    let toolchains = match requested {
+        Some(toolchain) => vec![toolchain],
+        None => WasmToolchain::ALL.to_vec(),
+    };
+    let Some((toolchain, program)) = toolchains
+        .into_iter()
+        .find_map(|toolchain| toolchain_program(toolchain).map(|program| (toolchain, program)))
+    else {
+        return Err(match requested {
+            Some(toolchain) => format!("WebAssembly toolchain {} is not installed", toolchain),
+            None => "No WebAssembly toolchain found, install emcc, docker, podman or wasi-sdk"
+                .to_string(),
+        });
+    };
+
+    let tree_sitter =
+        find_executable("tree-sitter").filter(|_| toolchain != WasmToolchain::WasiSdk);
+    let mut version = program_version(&program).await;
+    if let Some(tree_sitter) = &tree_sitter {
+        version = format!("{} ({})", program_version(tree_sitter).await, version);
+    }
+
+    Ok(WasmCompiler {
+        toolchain,
+        program: tree_sitter.clone().unwrap_or(program),
+        version,
+        uses_tree_sitter_cli: tree_sitter.is_some(),
+    })
+}
+
+fn toolchain_program(toolchain: WasmToolchain) -> Option<PathBuf> {
+    match toolchain {
+        WasmToolchain::Emcc => find_executable("emcc"),
+        WasmToolchain::Docker => find_executable("docker"),
+        WasmToolchain::Podman => find_executable("podman"),
+        WasmToolchain::WasiSdk => {
+            let wasi_sdk_path = env::var_os("WASI_SDK_PATH")
+                .map(PathBuf::from)
+                .unwrap_or(PathBuf::from("/opt/wasi-sdk"));
+            Some(wasi_sdk_path.join("bin").join("clang")).filter(|clang| clang.is_file())
+        }
+    }
+}
+
+pub(crate) fn find_executable(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|directory| directory.join(name))
+        .find(|path| path.is_file())
+}
+
+async fn program_version(program: &Path) -> String {
+    match tokio::process::Command::new(program)
+        .arg("--version")
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Compiles a grammar to a WebAssembly artifact, returning the command that was run.
+pub async fn build_wasm(
+    compiler: &WasmCompiler,
+    language: &Language,
+    grammar_directory: &Path,
+    output: &Path,
+) -> Result<String, String> {
+    let output = std::path::absolute(output)
+        .map_err(|e| format!("Invalid output path {}: {}", output.display(), e))?;
+    let grammar_directory = std::path::absolute(grammar_directory)
+        .map_err(|e| format!("Invalid path {}: {}", grammar_directory.display(), e))?;
+
+    let arguments = if compiler.uses_tree_sitter_cli {
+        let mut arguments = vec!["build".to_string(), "--wasm".to_string()];
+        if matches!(
+            compiler.toolchain,
+            WasmToolchain::Docker | WasmToolchain::Podman
+        ) {
+            arguments.push("--docker".to_string());
+        }
+        arguments.extend(["-o".to_string(), output.display().to_string()]);
+        arguments
+    } else {
+        direct_arguments(compiler.toolchain, language, &grammar_directory, &output)?
+    };
+
+    let command = format!("{} {}", compiler.program.display(), arguments.join(" "));
+    let status = tokio::process::Command::new(&compiler.program)
+        .current_dir(&grammar_directory)
+        .args(&arguments)
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => Ok(command),
+        Ok(status) => Err(format!(
+            "Command exited with status: {}, for language: {}: {}",
+            status, language.name, command
+        )),
+        Err(e) => Err(format!(
+            "Failed to execute command: {}, for language: {}: {}",
+            e, language.name, command
+        )),
+    }
+}
+
+/// Arguments compiling the parser and scanner of every grammar in the repository to a single side
+/// module exporting their `tree_sitter_<name>` functions, mirroring what `tree-sitter build --wasm`
+/// does for each of them.
+fn direct_arguments(
+    toolchain: WasmToolchain,
+    language: &Language,
+    grammar_directory: &Path,
+    output: &Path,
+) -> Result<Vec<String>, String> {
+    let units = grammar_units(language, grammar_directory);
+    if units.is_empty() {
+        return Err(format!(
+            "No src/parser.c to compile for language: {}",
+            language.name
+        ));
+    }
+
+    let mut sources = Vec::new();
+    let mut include_directories = Vec::new();
+    let mut symbols = Vec::new();
+    for unit in &units {
+        let src = unit.src();
+        let parser = format!("{}/parser.c", src);
+        if !grammar_directory.join(&parser).is_file() {
+            return Err(format!(
+                "No {} to compile for language: {}",
+                parser, language.name
+            ));
+        }
+        sources.push(parser);
+        for scanner in ["scanner.c", "scanner.cc"] {
+            let scanner = format!("{}/{}", src, scanner);
+            if grammar_directory.join(&scanner).is_file() {
+                sources.push(scanner);
+            }
+        }
+        include_directories.extend(["-I".to_string(), src]);
+        symbols.push(format!("tree_sitter_{}", unit.symbol));
+    }
+    // C++ scanners need the C++ standard library linked in
+    let has_cpp_scanner = sources.iter().any(|source| source.ends_with(".cc"));
+
+    let emcc_arguments = |output: String| {
+        let mut arguments = vec![
+            "-o".to_string(),
+            output,
+            "-Os".to_string(),
+            "-fno-exceptions".to_string(),
+            "-s".to_string(),
+            "WASM=1".to_string(),
+            "-s".to_string(),
+            "SIDE_MODULE=2".to_string(),
+            "-s".to_string(),
+            "TOTAL_MEMORY=33554432".to_string(),
+            "-s".to_string(),
+            "NODEJS_CATCH_EXIT=0".to_string(),
+            "-s".to_string(),
+            format!(
+                "EXPORTED_FUNCTIONS=[{}]",
+                symbols
+                    .iter()
+                    .map(|symbol| format!("\"_{}\"", symbol))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        ];
+        arguments.extend(include_directories.iter().cloned());
+        arguments.extend(sources.iter().cloned());
+        arguments
+    };
+
+    let arguments = match toolchain {
+        WasmToolchain::Emcc => emcc_arguments(output.display().to_string()),
+        WasmToolchain::Docker | WasmToolchain::Podman => {
+            let output_directory = output.parent().unwrap_or(Path::new("."));
+            let output_file = output
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mut arguments = vec![
+                "run".to_string(),
+                "--rm".to_string(),
+                "-v".to_string(),
+                format!("{}:/src:Z", grammar_directory.display()),
+                "-v".to_string(),
+                format!("{}:/out:Z", output_directory.display()),
+                "-w".to_string(),
+                "/src".to_string(),
+                EMSCRIPTEN_IMAGE.to_string(),
+                "emcc".to_string(),
+            ];
+            arguments.extend(emcc_arguments(format!("/out/{}", output_file)));
+            arguments
+        }
+        WasmToolchain::WasiSdk => {
+            let mut arguments = vec![
+                "--target=wasm32-unknown-wasi".to_string(),
+                "-Os".to_string(),
+                "-fPIC".to_string(),
+                "-shared".to_string(),
+            ];
+            if !has_cpp_scanner {
+                arguments.push("-nostdlib".to_string());
+            }
+            arguments.extend([
+                "-Wl,--experimental-pic".to_string(),
+                "-Wl,--allow-undefined".to_string(),
+            ]);
+            arguments.extend(
+                symbols
+                    .iter()
+                    .map(|symbol| format!("-Wl,--export={}", symbol)),
+            );
+            arguments.extend(include_directories.iter().cloned());
+            arguments.extend(["-o".to_string(), output.display().to_string()]);
+            arguments.extend(sources.iter().cloned());
+            arguments
+        }
+    };
+    Ok(arguments)
+}
+
+/// Returns the name the grammar was generated with, which its entry point is named after.
+pub fn grammar_symbol_name(language: &Language, grammar_directory: &Path) -> String {
+    fs::read_to_string(grammar_directory.join("src/grammar.json"))
+        .ok()
+        .and_then(|grammar| serde_json::from_str::<serde_json::Value>(&grammar).ok())
+        .and_then(|grammar| grammar["name"].as_str().map(|name| name.to_string()))
+        .unwrap_or_else(|| {
+            language
+                .name
+                .trim_start_matches("tree-sitter-")
+                .replace('-', "_")
+        })
+}
+
+/// How a WebAssembly artifact was last built.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BuiltArtifact {
+    /// Key identifying the build inputs, see `build_key`
+    pub key: String,
+    pub toolchain: String,
+    /// Command the artifact was compiled with
+    pub command: String,
+}
+
+/// Inputs the WebAssembly artifacts were last built from, stored as `.build-cache.toml` in the
+/// WebAssembly directory.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BuildCache {
+    /// How each artifact was built, keyed by its file name
+    #[serde(default)]
+    pub artifacts: BTreeMap<String, BuiltArtifact>,
+}
+
+impl BuildCache {
+    fn path(wasm_directory: &Path) -> PathBuf {
+        wasm_directory.join(".build-cache.toml")
+    }
+
+    pub fn read(wasm_directory: &Path) -> Self {
+        fs::read_to_string(Self::path(wasm_directory))
+            .ok()
+            .and_then(|cache_contents| toml::from_str(&cache_contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, wasm_directory: &Path) {
+        let cache_contents = toml::to_string_pretty(self).expect("Failed to serialize to TOML");
+        fs::write(Self::path(wasm_directory), cache_contents).expect("Failed to write build cache");
+    }
+
+    /// Whether an artifact exists and was built from inputs with the same build key.
+    pub fn is_fresh(&self, wasm_directory: &Path, artifact: &str, build_key: &str) -> bool {
+        wasm_directory.join(artifact).is_file()
+            && self
+                .artifacts
+                .get(artifact)
+                .is_some_and(|built| built.key == build_key)
+    }
+}
+
+/// Returns a key identifying the inputs of a grammar's build: the contents of its sources and
+/// the toolchain compiling them.
+pub fn build_key(
+    language: &Language,
+    grammar_directory: &Path,
+    compiler: &WasmCompiler,
+) -> Result<String, String> {
+    let mut inputs = format!("{}\n{}\n", compiler.toolchain, compiler.version);
+    let input_directories = grammar_units(language, grammar_directory)
+        .into_iter()
+        .map(|unit| unit.src())
+        .chain(
+            BUILD_INPUT_DIRECTORIES
+                .iter()
+                .map(|directory| directory.to_string()),
+        );
+    for input_directory in input_directories {
+        let input_directory = grammar_directory.join(input_directory);
+        if input_directory.is_dir() {
+            let digest = tree_sha256(&input_directory)
+                .map_err(|e| format!("Failed to hash {}: {}", input_directory.display(), e))?;
+            inputs.push_str(&format!("{}\n", digest));
+        } else {
+            inputs.push_str("-\n");
+        }
+    }
+    Ok(sha256(inputs.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiler(version: &str) -> WasmCompiler {
+        WasmCompiler {
+            toolchain: WasmToolchain::Emcc,
+            program: PathBuf::from("emcc"),
+            version: version.to_string(),
+            uses_tree_sitter_cli: false,
+        }
+    }
+
+    #[test]
+    fn rebuilds_when_sources_or_toolchain_change() {
+        let root = std::env::temp_dir().join(format!("build-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let grammar_directory = root.join("tree-sitter-test");
+        let wasm_directory = root.join("wasm");
+        fs::create_dir_all(grammar_directory.join("src")).unwrap();
+        fs::create_dir_all(&wasm_directory).unwrap();
+        fs::write(grammar_directory.join("src/parser.c"), "int x;").unwrap();
+        let language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        let artifact = "test.wasm";
+
+        let key = build_key(&language, &grammar_directory, &compiler("1.0")).unwrap();
+        let mut cache = BuildCache::default();
+        let missing_entry = cache.is_fresh(&wasm_directory, artifact, &key);
+        cache.artifacts.insert(
+            artifact.to_string(),
+            BuiltArtifact {
+                key: key.clone(),
+                toolchain: "emcc".to_string(),
+                command: "emcc".to_string(),
+            },
+        );
+        let missing_artifact = cache.is_fresh(&wasm_directory, artifact, &key);
+        fs::write(wasm_directory.join(artifact), "\0asm").unwrap();
+        cache.write(&wasm_directory);
+        let cache = BuildCache::read(&wasm_directory);
+        let fresh = cache.is_fresh(&wasm_directory, artifact, &key);
+
+        let same_key = build_key(&language, &grammar_directory, &compiler("1.0")).unwrap();
+        let toolchain_key = build_key(&language, &grammar_directory, &compiler("2.0")).unwrap();
+        fs::write(grammar_directory.join("src/scanner.c"), "int y;").unwrap();
+        let source_key = build_key(&language, &grammar_directory, &compiler("1.0")).unwrap();
+        let fresh_for_toolchain = cache.is_fresh(&wasm_directory, artifact, &toolchain_key);
+        let fresh_for_sources = cache.is_fresh(&wasm_directory, artifact, &source_key);
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(!missing_entry);
+        assert!(!missing_artifact);
+        assert!(fresh);
+        assert_eq!(same_key, key);
+        assert!(!fresh_for_toolchain);
+        assert!(!fresh_for_sources);
+    }
+}
@@ -0,0 +1,285 @@
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::abi::AbiRange;
+use crate::hashing::sha256;
+use crate::licenses::detect_license;
+use crate::licenses::find_license_files;
+use crate::licenses::read_license_texts;
+use crate::lockfile::grammar_commit;
+use crate::lockfile::Lockfile;
+use crate::metadata::grammar_units;
+use crate::metadata::read_query;
+use crate::metadata::root_grammar_metadata;
+use crate::metadata::select_query_flavor;
+use crate::validate::artifact_file_names;
+use crate::validate::validate_wasm_grammars;
+use crate::Language;
+
+/// Queries shipped with every grammar in a bundle, if the grammar has them.
+pub const BUNDLE_QUERIES: [&str; 5] = ["highlights", "injections", "locals", "folds", "indents"];
+
+/// A file in a bundle, relative to the bundle root.
+#[derive(Debug, Serialize, Clone)]
+pub struct BundleFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+/// What a frontend needs to know about a language before loading its grammar, written to the
+/// `metadata.json` of its folder.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleMetadata {
+    pub language: String,
+    pub name: String,
+    pub git: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    pub aliases: Vec<String>,
+    pub file_types: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub injection_regex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_line_regex: Option<String>,
+    pub abi_version: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BundledLanguage {
+    #[serde(flatten)]
+    pub metadata: BundleMetadata,
+    pub wasm: BundleFile,
+    pub queries: BTreeMap<String, BundleFile>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "licenseFile")]
+    pub license_file: Option<BundleFile>,
+    #[serde(rename = "metadataFile")]
+    pub metadata_file: BundleFile,
+}
+
+/// The `index.json` at the root of a bundle. Besides the languages, it maps aliases, file types
+/// and injection regexes to the languages they select, so a frontend can pick and lazy-load a
+/// grammar without fetching any other file.
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIndex {
+    pub version: String,
+    pub languages: BTreeMap<String, BundledLanguage>,
+    pub aliases: BTreeMap<String, String>,
+    /// File types claimed by more than one grammar, e.g. `h`, list every language in name order
+    pub file_types: BTreeMap<String, Vec<String>>,
+    /// Injection regexes shared by several grammars list every language in name order as well
+    pub injection_regexes: BTreeMap<String, Vec<String>>,
+}
+
+/// The contents of a bundle: its index and the files it references, keyed by path.
+pub struct Bundle {
+    pub index: BundleIndex,
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Bundle {
+    fn add_file(&mut self, path: String, contents: Vec<u8>) -> BundleFile {
+        let file = BundleFile {
+            path: path.clone(),
+            sha256: sha256(&contents),
+        };
+        self.files.insert(path, contents);
+        file
+    }
+}
+
+/// Collects the validated WebAssembly artifact, queries, license and metadata of every language
+/// with an artifact in `wasm_directory` into a bundle with one folder per language. Without a
+/// `version`, the bundle is versioned by a digest of its contents. Queries are taken in
+/// `query_flavor`, or the flavor configured for the language, where the grammar has it.
+pub fn create_bundle(
+    languages: &BTreeMap<String, Language>,
+    lockfile: &Lockfile,
+    abi: &AbiRange,
+    directory: &Path,
+    wasm_directory: &Path,
+    version: Option<String>,
+    query_flavor: Option<&str>,
+) -> Result<Bundle, String> {
+    let mut bundle = Bundle {
+        index: BundleIndex::default(),
+        files: BTreeMap::new(),
+    };
+    let mut errors = Vec::new();
+
+    for (language_name, language) in languages {
+        let Some(artifact) = artifact_file_names(language_name, language)
+            .into_iter()
+            .map(|file_name| wasm_directory.join(file_name))
+            .find(|artifact| artifact.is_file())
+        else {
+            println!("Skipping {}, it has no WebAssembly artifact", language_name);
+            continue;
+        };
+
+        let grammar_directory = directory.join(&language.name);
+        let units = grammar_units(language, &grammar_directory);
+        let abi_version = match validate_wasm_grammars(&artifact, &units, abi) {
+            Ok(abi_version) => abi_version,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let wasm = fs::read(&artifact)
+            .map_err(|e| format!("Failed to read {}: {}", artifact.display(), e))?;
+        let wasm = bundle.add_file(format!("{}/{}.wasm", language_name, language.name), wasm);
+
+        let grammar = root_grammar_metadata(&grammar_directory).unwrap_or_default();
+        let flavor = select_query_flavor(language, &grammar_directory, query_flavor);
+        let mut queries = BTreeMap::new();
+        for kind in BUNDLE_QUERIES {
+            if let Some(query) = read_query(&grammar_directory, &grammar, kind, flavor.as_deref()) {
+                let file = bundle.add_file(
+                    format!("{}/{}.scm", language_name, kind),
+                    query.into_bytes(),
+                );
+                queries.insert(kind.to_string(), file);
+            }
+        }
+
+        let license_files = find_license_files(&grammar_directory);
+        let license = read_license_texts(&grammar_directory, &license_files);
+        let license_file = license.as_ref().map(|text| {
+            bundle.add_file(
+                format!("{}/LICENSE", language_name),
+                text.clone().into_bytes(),
+            )
+        });
+
+        let commit = grammar_commit(lockfile, language_name, language);
+        let metadata = BundleMetadata {
+            language: language_name.clone(),
+            name: language.name.clone(),
+            git: language.git.clone(),
+            commit,
+            license: detect_license(&grammar_directory, &license_files),
+            scope: grammar.scope.clone(),
+            aliases: language.aliases.clone(),
+            file_types: grammar.file_types.clone(),
+            injection_regex: grammar.injection_regex.clone(),
+            first_line_regex: grammar.first_line_regex.clone(),
+            abi_version,
+        };
+        let metadata_json =
+            serde_json::to_string_pretty(&metadata).expect("Failed to serialize to JSON") + "\n";
+        let metadata_file = bundle.add_file(
+            format!("{}/metadata.json", language_name),
+            metadata_json.into_bytes(),
+        );
+
+        for alias in &language.aliases {
+            bundle
+                .index
+                .aliases
+                .insert(alias.clone(), language_name.clone());
+        }
+        for file_type in &grammar.file_types {
+            bundle
+                .index
+                .file_types
+                .entry(file_type.clone())
+                .or_default()
+                .push(language_name.clone());
+        }
+        if let Some(injection_regex) = &grammar.injection_regex {
+            bundle
+                .index
+                .injection_regexes
+                .entry(injection_regex.clone())
+                .or_default()
+                .push(language_name.clone());
+        }
+        bundle.index.languages.insert(
+            language_name.clone(),
+            BundledLanguage {
+                metadata,
+                wasm,
+                queries,
+                license_file,
+                metadata_file,
+            },
+        );
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    bundle.index.version = version.unwrap_or_else(|| {
+        let digests: String = bundle.files.values().map(|file| sha256(file)).collect();
+        sha256(digests.as_bytes())[..12].to_string()
+    });
+    Ok(bundle)
+}
+
+/// Writes a bundle to `<output>/tree-sitter-grammars-<version>`, replacing an earlier bundle of
+/// the same version, or to `<output>/tree-sitter-grammars-<version>.tar.zst` if `archive` is set.
+/// Returns the path written to.
+pub fn write_bundle(bundle: &Bundle, output: &Path, archive: bool) -> Result<String, String> {
+    let root = format!("tree-sitter-grammars-{}", bundle.index.version);
+    let index =
+        serde_json::to_string_pretty(&bundle.index).expect("Failed to serialize to JSON") + "\n";
+    let mut files: Vec<(&str, &[u8])> = vec![("index.json", index.as_bytes())];
+    files.extend(
+        bundle
+            .files
+            .iter()
+            .map(|(path, contents)| (path.as_str(), contents.as_slice())),
+    );
+
+    fs::create_dir_all(output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    if archive {
+        let archive_path = output.join(format!("{}.tar.zst", root));
+        write_archive(&archive_path, &root, &files)
+            .map_err(|e| format!("Failed to write {}: {}", archive_path.display(), e))?;
+        return Ok(archive_path.display().to_string());
+    }
+
+    let bundle_directory = output.join(&root);
+    if bundle_directory.exists() {
+        fs::remove_dir_all(&bundle_directory)
+            .map_err(|e| format!("Failed to remove {}: {}", bundle_directory.display(), e))?;
+    }
+    for (path, contents) in files {
+        let path = bundle_directory.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(bundle_directory.display().to_string())
+}
+
+/// Writes the files into a zstd compressed tarball below a single root directory, without
+/// timestamps or ownership, so bundles of the same contents are identical.
+fn write_archive(archive_path: &Path, root: &str, files: &[(&str, &[u8])]) -> std::io::Result<()> {
+    let encoder = zstd::Encoder::new(fs::File::create(archive_path)?, 0)?;
+    let mut builder = tar::Builder::new(encoder);
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{}/{}", root, path), *contents)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use crate::query::parse_query;
+use crate::query::Name;
+use crate::query::PatternKind;
+use crate::query::PredicateArgument;
+use crate::query::QueryError;
+
+/// The global `[captures]` table of the languages file: the canonical highlight capture names
+/// our theme covers, and renames of the ones grammars use instead.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureVocabulary {
+    /// Canonical capture names without `@`, e.g. `function.method`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<String>,
+    /// Capture names mapped to a canonical one, e.g. `method = "function.method"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rename: BTreeMap<String, String>,
+}
+
+impl CaptureVocabulary {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Maps a capture name to the canonical vocabulary: names in it are kept and renamed names
+    /// replaced, and otherwise the name is shortened by its last `.` segment until it matches,
+    /// the same fallback themes use, so `keyword.function.builtin` can become `keyword.function`.
+    pub fn canonical(&self, capture: &str) -> Option<String> {
+        let mut name = capture;
+        loop {
+            if let Some(renamed) = self.rename.get(name) {
+                return Some(renamed.clone());
+            }
+            if self.vocabulary.iter().any(|canonical| canonical == name) {
+                return Some(name.to_string());
+            }
+            name = name.rsplit_once('.')?.0;
+        }
+    }
+}
+
+/// Whether a capture only serves predicates, like `@_name`, rather than naming a highlight.
+pub fn is_private_capture(capture: &str) -> bool {
+    capture.starts_with('_')
+}
+
+/// Returns every capture of a query, in both patterns and predicates, without `@`.
+pub fn query_captures(source: &str) -> Result<BTreeSet<String>, QueryError> {
+    Ok(capture_names(source)?
+        .into_iter()
+        .map(|name| name.text)
+        .collect())
+}
+
+/// Rewrites the captures of a query to the canonical vocabulary, including the ones predicates
+/// refer to so they keep matching. Captures without a canonical name are kept and returned.
+pub fn rewrite_captures(
+    source: &str,
+    vocabulary: &CaptureVocabulary,
+) -> Result<(String, BTreeSet<String>), QueryError> {
+    let mut unmapped = BTreeSet::new();
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    for name in capture_names(source)? {
+        if is_private_capture(&name.text) {
+            continue;
+        }
+        match vocabulary.canonical(&name.text) {
+            Some(canonical) if canonical != name.text => {
+                replacements.push((name.span, format!("@{}", canonical)))
+            }
+            Some(_) => {}
+            None => {
+                unmapped.insert(name.text);
+            }
+        }
+    }
+
+    replacements.sort_by_key(|(span, _)| span.start);
+    let mut rewritten = String::with_capacity(source.len());
+    let mut offset = 0;
+    for (span, replacement) in replacements {
+        rewritten.push_str(&source[offset..span.start]);
+        rewritten.push_str(&replacement);
+        offset = span.end;
+    }
+    rewritten.push_str(&source[offset..]);
+    Ok((rewritten, unmapped))
+}
+
+/// Returns every capture of a query with its span, which includes the `@`.
+fn capture_names(source: &str) -> Result<Vec<Name>, QueryError> {
+    let mut names = Vec::new();
+    for pattern in parse_query(source)? {
+        pattern.walk(&mut |pattern| {
+            names.extend(pattern.captures.iter().cloned());
+            if let PatternKind::Predicate(predicate) = &pattern.kind {
+                for argument in &predicate.arguments {
+                    if let PredicateArgument::Capture(capture) = argument {
+                        names.push(capture.clone());
+                    }
+                }
+            }
+        });
+    }
+    Ok(names)
+}
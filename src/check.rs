@@ -0,0 +1,482 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use toml::Spanned;
+
+use crate::dependencies::dependency_cycle;
+use crate::Language;
+use crate::LanguageGrammarsTOML;
+
+/// A single problem found in the languages file, located by line and column (both 1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Why the languages file, or its lockfile, could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The file could not be read
+    Read { path: PathBuf, message: String },
+    /// The file is not valid TOML, or not shaped like a languages file or lockfile
+    Parse {
+        path: PathBuf,
+        diagnostic: Diagnostic,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Read { path, message } => {
+                write!(f, "Failed to read {}: {}", path.display(), message)
+            }
+            LoadError::Parse { path, diagnostic } => write!(f, "{}:{}", path.display(), diagnostic),
+        }
+    }
+}
+
+/// Reads the languages file at `file_path`.
+pub fn read_languages_file(file_path: &Path) -> Result<String, LoadError> {
+    fs::read_to_string(file_path).map_err(|e| LoadError::Read {
+        path: file_path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+/// Parses the contents of the languages file at `file_path`, locating any error by line and
+/// column.
+pub fn parse_languages_file<T: serde::de::DeserializeOwned>(
+    file_path: &Path,
+    toml_contents: &str,
+) -> Result<T, LoadError> {
+    toml::from_str(toml_contents).map_err(|e| LoadError::Parse {
+        path: file_path.to_path_buf(),
+        diagnostic: toml_error_diagnostic(toml_contents, &e),
+    })
+}
+
+fn toml_error_diagnostic(toml_contents: &str, error: &toml::de::Error) -> Diagnostic {
+    let (line, column) = line_and_column(toml_contents, error.span().map_or(0, |span| span.start));
+    Diagnostic {
+        line,
+        column,
+        message: error.message().to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+struct SpannedLanguage {
+    name: Spanned<String>,
+    git: Spanned<String>,
+    hash: Option<Spanned<String>>,
+    #[serde(default)]
+    dependencies: Vec<Spanned<String>>,
+    #[serde(default)]
+    aliases: Vec<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct SpannedLanguageGrammarsTOML {
+    languages: BTreeMap<Spanned<String>, SpannedLanguage>,
+}
+
+/// URL schemes `git2` is able to clone from.
+const SUPPORTED_SCHEMES: [&str; 5] = ["https", "http", "ssh", "git", "file"];
+
+/// Reads the languages file at `file_path` and reports every problem found in it.
+pub fn check_languages_toml(file_path: &Path) -> Result<Vec<Diagnostic>, LoadError> {
+    Ok(check_languages_toml_str(&read_languages_file(file_path)?))
+}
+
+/// Reports every problem found in the contents of a languages file.
+pub fn check_languages_toml_str(toml_contents: &str) -> Vec<Diagnostic> {
+    let diagnostic = |span: Range<usize>, message: String| {
+        let (line, column) = line_and_column(toml_contents, span.start);
+        Diagnostic {
+            line,
+            column,
+            message,
+        }
+    };
+
+    // the file must load before its entries are checked, which also rejects unknown keys
+    if let Err(e) = toml::from_str::<LanguageGrammarsTOML>(toml_contents) {
+        return vec![toml_error_diagnostic(toml_contents, &e)];
+    }
+    let languages: SpannedLanguageGrammarsTOML = match toml::from_str(toml_contents) {
+        Ok(languages) => languages,
+        Err(e) => return vec![toml_error_diagnostic(toml_contents, &e)],
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut git_urls: HashMap<String, &str> = HashMap::new();
+    let mut directories: HashMap<String, &str> = HashMap::new();
+
+    // report duplicates on the entry that appears later in the file
+    let mut entries: Vec<_> = languages.languages.iter().collect();
+    entries.sort_by_key(|(key, _)| key.span().start);
+
+    for (key, language) in entries {
+        let key_name = key.get_ref().as_str();
+        let name = language.name.get_ref();
+        let git = language.git.get_ref();
+
+        let expected_name = format!("{}{}", "tree-sitter-", key_name);
+        if *name != expected_name {
+            diagnostics.push(diagnostic(
+                language.name.span(),
+                format!(
+                    "name '{}' of language '{}' should be '{}'",
+                    name, key_name, expected_name
+                ),
+            ));
+        }
+
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            diagnostics.push(diagnostic(
+                language.name.span(),
+                format!(
+                    "name '{}' of language '{}' is not a valid directory name",
+                    name, key_name
+                ),
+            ));
+        }
+
+        // grammar directories may end up on a case-insensitive file system
+        if let Some(other) = directories.insert(name.to_lowercase(), key_name) {
+            diagnostics.push(diagnostic(
+                language.name.span(),
+                format!(
+                    "directory '{}' of language '{}' collides with language '{}'",
+                    name, key_name, other
+                ),
+            ));
+        }
+
+        match url_scheme(git) {
+            Some(scheme) if !SUPPORTED_SCHEMES.contains(&scheme.as_str()) => {
+                diagnostics.push(diagnostic(
+                    language.git.span(),
+                    format!(
+                        "unsupported URL scheme '{}' for language '{}'",
+                        scheme, key_name
+                    ),
+                ))
+            }
+            None => diagnostics.push(diagnostic(
+                language.git.span(),
+                format!("invalid git URL '{}' for language '{}'", git, key_name),
+            )),
+            _ => (),
+        }
+
+        if let Some(other) = git_urls.insert(normalize_git_url(git), key_name) {
+            diagnostics.push(diagnostic(
+                language.git.span(),
+                format!(
+                    "git URL '{}' of language '{}' is already used by language '{}'",
+                    git, key_name, other
+                ),
+            ));
+        }
+
+        if let Some(hash) = &language.hash {
+            let commit_hash = hash.get_ref();
+            if commit_hash.len() != 40 || !commit_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                diagnostics.push(diagnostic(
+                    hash.span(),
+                    format!(
+                        "hash '{}' of language '{}' is not a 40 character hexadecimal commit hash",
+                        commit_hash, key_name
+                    ),
+                ));
+            }
+        }
+    }
+
+    // aliases resolve to a single language, so they may not shadow language names or each other
+    let mut aliases: HashMap<&str, &str> = HashMap::new();
+    let mut entries: Vec<_> = languages.languages.iter().collect();
+    entries.sort_by_key(|(key, _)| key.span().start);
+    for (key, language) in entries {
+        let key_name = key.get_ref().as_str();
+        for alias in &language.aliases {
+            let alias_name = alias.get_ref().as_str();
+            let other = if languages
+                .languages
+                .keys()
+                .any(|key| key.get_ref() == alias_name)
+            {
+                Some(alias_name)
+            } else {
+                aliases.insert(alias_name, key_name)
+            };
+            if let Some(other) = other {
+                diagnostics.push(diagnostic(
+                    alias.span(),
+                    format!(
+                        "alias '{}' of language '{}' is already used by language '{}'",
+                        alias_name, key_name, other
+                    ),
+                ));
+            }
+        }
+    }
+
+    let plain_languages: BTreeMap<String, Language> = languages
+        .languages
+        .iter()
+        .map(|(key, language)| {
+            let mut plain_language = Language::new(
+                language.name.get_ref().clone(),
+                language.git.get_ref().clone(),
+                None,
+            );
+            plain_language.dependencies = language
+                .dependencies
+                .iter()
+                .map(|dependency| dependency.get_ref().clone())
+                .collect();
+            (key.get_ref().clone(), plain_language)
+        })
+        .collect();
+    for (key, language) in &languages.languages {
+        let key_name = key.get_ref();
+        for dependency in &language.dependencies {
+            if !plain_languages.contains_key(dependency.get_ref()) {
+                diagnostics.push(diagnostic(
+                    dependency.span(),
+                    format!(
+                        "language '{}' depends on unknown language '{}'",
+                        key_name,
+                        dependency.get_ref()
+                    ),
+                ));
+            }
+        }
+    }
+    let cycle = dependency_cycle(&plain_languages);
+    for (key, language) in &languages.languages {
+        if let Some(dependency) = language
+            .dependencies
+            .iter()
+            .find(|dependency| cycle.contains(dependency.get_ref()))
+        {
+            diagnostics.push(diagnostic(
+                dependency.span(),
+                format!(
+                    "language '{}' is part of a dependency cycle through '{}'",
+                    key.get_ref(),
+                    dependency.get_ref()
+                ),
+            ));
+        }
+    }
+
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// Returns the scheme of a git URL, treating scp-like URLs (`git@host:path`) as `ssh` and
+/// anything else as a local path, which `git2` clones like a `file` URL.
+fn url_scheme(url: &str) -> Option<String> {
+    if let Some((scheme, rest)) = url.split_once("://") {
+        if scheme.is_empty() || rest.is_empty() {
+            return None;
+        }
+        return Some(scheme.to_lowercase());
+    }
+    match url.split_once(':') {
+        Some((host, path)) if host.contains('@') && !path.is_empty() => Some("ssh".to_string()),
+        _ if url.trim().is_empty() => None,
+        _ => Some("file".to_string()),
+    }
+}
+
+/// Reduces a git URL to `host/path` so the same repository is recognised across schemes.
+fn normalize_git_url(url: &str) -> String {
+    let without_scheme = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        None => url.replacen(':', "/", 1),
+    };
+    let without_user = match without_scheme.split_once('@') {
+        Some((_, rest)) => rest,
+        None => &without_scheme,
+    };
+    without_user
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_lowercase()
+}
+
+pub(crate) fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.chars().count(), |newline| {
+            before[newline + 1..].chars().count()
+        })
+        + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(toml_contents: &str) -> Vec<(usize, usize, String)> {
+        check_languages_toml_str(toml_contents)
+            .into_iter()
+            .map(|d| (d.line, d.column, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn locates_problems_by_line_and_column() {
+        let toml_contents = "\
+[languages.c]
+name = \"tree-sitter-c\"
+git = \"https://github.com/tree-sitter/tree-sitter-c\"
+hash = \"abc\"
+
+[languages.cpp]
+name = \"cpp\"
+git = \"svn://example.com/tree-sitter-cpp\"
+";
+        assert_eq!(
+            messages(toml_contents),
+            [
+                (
+                    4,
+                    8,
+                    "hash 'abc' of language 'c' is not a 40 character hexadecimal commit hash"
+                        .to_string()
+                ),
+                (
+                    7,
+                    8,
+                    "name 'cpp' of language 'cpp' should be 'tree-sitter-cpp'".to_string()
+                ),
+                (
+                    8,
+                    7,
+                    "unsupported URL scheme 'svn' for language 'cpp'".to_string()
+                ),
+            ]
+        );
+        assert_eq!(line_and_column("ab\ncdé\nf", 7), (2, 4));
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let diagnostics = messages(
+            "[languages.c]\nname = \"tree-sitter-c\"\ngit = \"https://example.com/c\"\nhsah = \"\"\n",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].0, diagnostics[0].1), (4, 1));
+        assert!(diagnostics[0].2.contains("unknown field `hsah`"));
+
+        let diagnostics = messages("[prnue]\nexclude = []\n[languages]\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].2.contains("unknown field `prnue`"));
+    }
+
+    #[test]
+    fn reports_dependency_cycles() {
+        let toml_contents = "\
+[languages.a]
+name = \"tree-sitter-a\"
+git = \"https://example.com/a\"
+dependencies = [\"b\"]
+
+[languages.b]
+name = \"tree-sitter-b\"
+git = \"https://example.com/b\"
+dependencies = [\"a\", \"c\"]
+";
+        assert_eq!(
+            messages(toml_contents),
+            [
+                (
+                    4,
+                    17,
+                    "language 'a' is part of a dependency cycle through 'b'".to_string()
+                ),
+                (
+                    9,
+                    17,
+                    "language 'b' is part of a dependency cycle through 'a'".to_string()
+                ),
+                (
+                    9,
+                    22,
+                    "language 'b' depends on unknown language 'c'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_aliases() {
+        let toml_contents = "\
+[languages.bash]
+name = \"tree-sitter-bash\"
+git = \"https://example.com/bash\"
+aliases = [\"sh\"]
+
+[languages.zsh]
+name = \"tree-sitter-zsh\"
+git = \"https://example.com/zsh\"
+aliases = [\"sh\", \"bash\"]
+";
+        assert_eq!(
+            messages(toml_contents),
+            [
+                (
+                    9,
+                    12,
+                    "alias 'sh' of language 'zsh' is already used by language 'bash'".to_string()
+                ),
+                (
+                    9,
+                    18,
+                    "alias 'bash' of language 'zsh' is already used by language 'bash'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_clonable_urls() {
+        for url in [
+            "https://github.com/tree-sitter/tree-sitter-c",
+            "git@github.com:tree-sitter/tree-sitter-c.git",
+            "file:///srv/git/tree-sitter-c",
+            "/srv/git/tree-sitter-c",
+            "../tree-sitter-c",
+        ] {
+            let toml_contents = format!(
+                "[languages.c]\nname = \"tree-sitter-c\"\ngit = \"{}\"\n",
+                url
+            );
+            assert_eq!(messages(&toml_contents), [], "{}", url);
+        }
+        assert_eq!(url_scheme(""), None);
+        assert_eq!(url_scheme("https://"), None);
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::node_types::FieldInfo;
+use crate::node_types::NodeType;
+use crate::node_types::NodeTypeRef;
+
+/// Names the generated module uses, which node types are named after with a `Node` suffix
+/// instead, e.g. `OptionNode` for `option`.
+const RESERVED_NAMES: &[&str] = &[
+    "AstNode", "Node", "None", "Option", "Self", "Sized", "Some", "Token", "Vec",
+];
+
+/// Rust keywords, which fields are named after with a raw identifier, e.g. `r#type`.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+/// Keywords that cannot be raw identifiers, which fields are named after with a trailing `_`.
+const PATH_KEYWORDS: [&str; 4] = ["self", "Self", "super", "crate"];
+
+/// Generates a Rust module with a type for every named node type of a grammar, built over
+/// `tree_sitter::Node`: a struct with accessors for the fields and children of each concrete
+/// node type, and an enum of the subtypes of each supertype. Fields and children holding
+/// several node types get an enum of their own, and anonymous nodes are wrapped in `Token`.
+pub fn generate_rust_ast(language_name: &str, node_types: &[NodeType]) -> String {
+    let mut names = TypeNames::default();
+    let named: BTreeMap<&str, &NodeType> = node_types
+        .iter()
+        .filter(|node_type| node_type.named)
+        .map(|node_type| (node_type.kind.as_str(), node_type))
+        .collect();
+    for kind in named.keys() {
+        names.add_node_type(kind);
+    }
+
+    let mut module = format!(
+        "// Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars codegen rust`.\n\n\
+         //! Typed syntax tree of the {} grammar, generated from its `node-types.json`.\n\n\
+         use tree_sitter::Node;\n\n",
+        language_name
+    );
+    module.push_str(PRELUDE);
+    if named.values().any(|node_type| node_type.children.is_some()) {
+        module.push_str(CHILDREN_HELPER);
+    }
+
+    for node_type in named.values() {
+        let name = names.node_types[&node_type.kind].clone();
+        if node_type.is_supertype() {
+            let enum_type = EnumType {
+                name: name.clone(),
+                description: format!("the `{}` supertype", node_type.kind),
+                variants: names.variants(&node_type.subtypes),
+            };
+            write_enum(&mut module, &enum_type);
+            continue;
+        }
+
+        let _ = writeln!(module, "/// A `{}` node", node_type.kind);
+        let _ = writeln!(
+            module,
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq)]\n\
+             pub struct {name}<'tree>(Node<'tree>);\n\n\
+             impl<'tree> AstNode<'tree> for {name}<'tree> {{\n    \
+                 fn cast(node: Node<'tree>) -> Option<Self> {{\n        \
+                     (node.is_named() && node.kind() == {kind:?}).then_some(Self(node))\n    \
+                 }}\n\n    \
+                 fn node(&self) -> Node<'tree> {{\n        \
+                     self.0\n    \
+                 }}\n\
+             }}\n",
+            name = name,
+            kind = node_type.kind,
+        );
+        if node_type.fields.is_empty() && node_type.children.is_none() {
+            continue;
+        }
+
+        // the types of fields and children holding several node types, written after the impl
+        let mut enums = Vec::new();
+        let _ = writeln!(module, "impl<'tree> {}<'tree> {{", name);
+        let mut first = true;
+        for (field, info) in &node_type.fields {
+            if !first {
+                module.push('\n');
+            }
+            first = false;
+            let item = names.item_type(&name, field, info, &mut enums);
+            let method = method_name(field);
+            if info.multiple {
+                let _ = writeln!(
+                    module,
+                    "    /// The nodes of the `{field}` field\n    \
+                     pub fn {method}(&self) -> Vec<{item}<'tree>> {{\n        \
+                         let mut cursor = self.0.walk();\n        \
+                         self.0\n            \
+                             .children_by_field_name({field:?}, &mut cursor)\n            \
+                             .filter_map({item}::cast)\n            \
+                             .collect()\n    \
+                     }}",
+                );
+            } else {
+                let _ = writeln!(
+                    module,
+                    "    /// The node of the `{field}` field{required}\n    \
+                     pub fn {method}(&self) -> Option<{item}<'tree>> {{\n        \
+                         self.0.child_by_field_name({field:?}).and_then({item}::cast)\n    \
+                     }}",
+                    required = match info.required {
+                        true => ", which is only missing in trees with errors",
+                        false => "",
+                    },
+                );
+            }
+        }
+        if let Some(children) = &node_type.children {
+            if !first {
+                module.push('\n');
+            }
+            let item = names.item_type(&name, "child", children, &mut enums);
+            let method = match node_type.fields.contains_key("children") {
+                true => "unnamed_children",
+                false => "children",
+            };
+            let _ = writeln!(
+                module,
+                "    /// The named children that are not in a field\n    \
+                 pub fn {method}(&self) -> Vec<{item}<'tree>> {{\n        \
+                     children_without_field(self.0)\n            \
+                         .into_iter()\n            \
+                         .filter_map({item}::cast)\n            \
+                         .collect()\n    \
+                 }}",
+            );
+        }
+        module.push_str("}\n\n");
+
+        for enum_type in &enums {
+            write_enum(&mut module, enum_type);
+        }
+    }
+    module.truncate(module.trim_end().len());
+    module.push('\n');
+    module
+}
+
+/// The part of every generated module that does not depend on the grammar.
+const PRELUDE: &str =
+    "/// A node of the typed syntax tree, wrapping a `tree_sitter::Node` of a known type.
+pub trait AstNode<'tree>: Sized {
+    /// Wraps a node if it has the type of `Self`.
+    fn cast(node: Node<'tree>) -> Option<Self>;
+
+    /// Returns the wrapped node.
+    fn node(&self) -> Node<'tree>;
+}
+
+/// An anonymous node, e.g. a keyword or an operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'tree>(Node<'tree>);
+
+impl<'tree> AstNode<'tree> for Token<'tree> {
+    fn cast(node: Node<'tree>) -> Option<Self> {
+        (!node.is_named()).then_some(Self(node))
+    }
+
+    fn node(&self) -> Node<'tree> {
+        self.0
+    }
+}
+
+";
+
+/// Collects the named children of a node outside fields, for the `children()` accessors.
+const CHILDREN_HELPER: &str = "fn children_without_field(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut children = Vec::new();
+    let mut cursor = node.walk();
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if cursor.field_name().is_none() && child.is_named() && !child.is_extra() {
+                children.push(child);
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    children
+}
+
+";
+
+/// An enum generated for a supertype, or for a field or the children of a node type holding
+/// several node types.
+#[derive(Debug, Clone)]
+struct EnumType {
+    name: String,
+    /// What the enum holds, completing "Any node of"
+    description: String,
+    /// The types of the variants, which are named after them
+    variants: Vec<String>,
+}
+
+fn write_enum(module: &mut String, enum_type: &EnumType) {
+    let name = &enum_type.name;
+    let _ = writeln!(module, "/// Any node of {}", enum_type.description);
+    let _ = writeln!(
+        module,
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq)]\npub enum {}<'tree> {{",
+        name
+    );
+    for variant in &enum_type.variants {
+        let _ = writeln!(module, "    {}({}<'tree>),", variant, variant);
+    }
+    let _ = writeln!(
+        module,
+        "}}\n\n\
+         impl<'tree> AstNode<'tree> for {name}<'tree> {{\n    \
+             fn cast(node: Node<'tree>) -> Option<Self> {{",
+    );
+    for variant in &enum_type.variants {
+        let _ = writeln!(
+            module,
+            "        if let Some(node) = {variant}::cast(node) {{\n            \
+                 return Some(Self::{variant}(node));\n        \
+             }}",
+        );
+    }
+    module.push_str(
+        "        None\n    }\n\n    fn node(&self) -> Node<'tree> {\n        match self {\n",
+    );
+    for variant in &enum_type.variants {
+        let _ = writeln!(
+            module,
+            "            Self::{}(node) => node.node(),",
+            variant
+        );
+    }
+    module.push_str("        }\n    }\n}\n\n");
+}
+
+/// The Rust names of the node types of a grammar and the enums generated for them, which are
+/// unique within the generated module.
+#[derive(Debug, Default)]
+struct TypeNames {
+    node_types: BTreeMap<String, String>,
+    taken: BTreeSet<String>,
+}
+
+impl TypeNames {
+    fn add_node_type(&mut self, kind: &str) {
+        let mut name = pascal_case(kind);
+        if RESERVED_NAMES.contains(&name.as_str()) {
+            name.push_str("Node");
+        }
+        let name = self.unique(name);
+        self.node_types.insert(kind.to_string(), name);
+    }
+
+    fn unique(&mut self, candidate: String) -> String {
+        let mut name = candidate.clone();
+        let mut suffix = 2;
+        while self.taken.contains(&name) || RESERVED_NAMES.contains(&name.as_str()) {
+            name = format!("{}{}", candidate, suffix);
+            suffix += 1;
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+
+    /// Returns the types of the variants of an enum of the given node types, with a single
+    /// `Token` for all anonymous ones.
+    fn variants(&self, types: &[NodeTypeRef]) -> Vec<String> {
+        let mut variants: Vec<String> = types
+            .iter()
+            .filter(|node_type| node_type.named)
+            .filter_map(|node_type| self.node_types.get(&node_type.kind).cloned())
+            .collect();
+        variants.sort();
+        variants.dedup();
+        if types.iter().any(|node_type| !node_type.named) {
+            variants.push("Token".to_string());
+        }
+        variants
+    }
+
+    /// Returns the type of the nodes of a field or of the children of a node type: the type of
+    /// its only node type, or an enum of all of them added to `enums`.
+    fn item_type(
+        &mut self,
+        parent: &str,
+        field: &str,
+        info: &FieldInfo,
+        enums: &mut Vec<EnumType>,
+    ) -> String {
+        let variants = self.variants(&info.types);
+        match variants.as_slice() {
+            [] => "Token".to_string(),
+            [item] => item.clone(),
+            _ => {
+                let candidate = format!("{}{}", parent, pascal_case(field));
+                let candidate = match self.taken.contains(&candidate) {
+                    true => format!("{}Field", candidate),
+                    false => candidate,
+                };
+                let name = self.unique(candidate);
+                let description = match field {
+                    "child" => format!("the children of `{}`", parent),
+                    field => format!("the `{}` field of `{}`", field, parent),
+                };
+                enums.push(EnumType {
+                    name: name.clone(),
+                    description,
+                    variants,
+                });
+                name
+            }
+        }
+    }
+}
+
+/// Converts the name of a node type to a Rust type name, e.g. `_type` to `Type` and
+/// `function_item` to `FunctionItem`.
+fn pascal_case(kind: &str) -> String {
+    let mut name: String = kind
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'N');
+    }
+    name
+}
+
+/// Converts the name of a field to a method name, which may have to be a raw identifier.
+fn method_name(field: &str) -> String {
+    let name: String = field
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    if PATH_KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_types_after_node_types() {
+        assert_eq!(pascal_case("function_item"), "FunctionItem");
+        assert_eq!(pascal_case("_type"), "Type");
+        assert_eq!(pascal_case("jsx-element"), "JsxElement");
+        assert_eq!(pascal_case("2d_array"), "N2dArray");
+        assert_eq!(pascal_case("$"), "N");
+
+        // node types are named in the order of their kinds, like the generator does
+        let mut names = TypeNames::default();
+        for kind in ["_option", "_type", "node", "option", "type"] {
+            names.add_node_type(kind);
+        }
+        let node_types: Vec<(&str, &str)> = names
+            .node_types
+            .iter()
+            .map(|(kind, name)| (kind.as_str(), name.as_str()))
+            .collect();
+        assert_eq!(
+            node_types,
+            [
+                ("_option", "OptionNode"),
+                ("_type", "Type"),
+                ("node", "NodeNode"),
+                ("option", "OptionNode2"),
+                ("type", "Type2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_keywords_in_method_names() {
+        assert_eq!(method_name("name"), "name");
+        assert_eq!(method_name("type"), "r#type");
+        assert_eq!(method_name("async"), "r#async");
+        assert_eq!(method_name("self"), "self_");
+        assert_eq!(method_name("Self"), "self_");
+        assert_eq!(method_name("crate"), "crate_");
+        assert_eq!(method_name("0"), "_0");
+        assert_eq!(method_name("return-type"), "return_type");
+    }
+
+    #[test]
+    fn generates_accessors_and_enums() {
+        let node_types: Vec<NodeType> = serde_json::from_str(
+            r#"[
+                {"type": "_expression", "named": true, "subtypes": [
+                    {"type": "identifier", "named": true},
+                    {"type": "number", "named": true}
+                ]},
+                {"type": "cast", "named": true, "fields": {
+                    "type": {"multiple": false, "required": true, "types": [
+                        {"type": "identifier", "named": true}
+                    ]},
+                    "value": {"multiple": false, "required": false, "types": [
+                        {"type": "identifier", "named": true},
+                        {"type": "number", "named": true},
+                        {"type": "-", "named": false}
+                    ]}
+                }},
+                {"type": "identifier", "named": true},
+                {"type": "number", "named": true},
+                {"type": "-", "named": false}
+            ]"#,
+        )
+        .unwrap();
+        let module = generate_rust_ast("test", &node_types);
+
+        assert!(module.contains("pub enum Expression<'tree> {"));
+        assert!(module.contains("pub fn r#type(&self) -> Option<Identifier<'tree>>"));
+        assert!(module.contains("pub fn value(&self) -> Option<CastValue<'tree>>"));
+        assert!(module.contains("pub enum CastValue<'tree> {"));
+        assert!(module.contains("    Token(Token<'tree>),"));
+        assert!(!module.contains("fn children_without_field"));
+    }
+}
@@ -0,0 +1,388 @@
+use regex::Captures;
+use regex::Regex;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static HEADER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?m)^(?P<equals>={3,})(?P<suffix1>[^=\r\n][^\r\n]*)?\r?\n(?P<name>(?:[^=\r\n][^\r\n]*\r?\n)+)={3,}(?P<suffix2>[^=\r\n][^\r\n]*)?\r?\n",
+    )
+    .unwrap()
+});
+static DIVIDER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^(?P<hyphens>-{3,})(?P<suffix>[^-\r\n][^\r\n]*)?\r?\n").unwrap()
+});
+static COMMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\s*;.*$").unwrap());
+static WHITESPACE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+static FIELD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r" [\w-]+: \(").unwrap());
+
+/// The attributes of a corpus test, given on the lines of its header after its name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorpusAttributes {
+    /// `:skip`, the test is not run
+    pub skip: bool,
+    /// `:error`, the input is expected to parse with errors, whatever the tree
+    pub error: bool,
+    /// `:fail-fast`, the remaining tests are not run if this one fails
+    pub fail_fast: bool,
+    /// `:cst`, the expected output is a concrete syntax tree
+    pub cst: bool,
+    /// `:language(name)`, the grammars of a repository with several the test is for
+    pub languages: Vec<String>,
+    /// `:platform(os)`, the operating systems the test runs on
+    pub platforms: Vec<String>,
+}
+
+/// A test of a corpus file: an input and the S-expression of the tree it parses to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusTest {
+    pub name: String,
+    /// Line of the test's header in the corpus file, 1-based
+    pub line: usize,
+    pub input: String,
+    /// The expected tree, with comments removed and whitespace normalized
+    pub expected: String,
+    pub attributes: CorpusAttributes,
+}
+
+impl CorpusTest {
+    /// Whether the test is run for the grammar named `symbol` on this platform.
+    pub fn applies_to(&self, symbol: &str) -> bool {
+        let attributes = &self.attributes;
+        !attributes.skip
+            && (attributes.languages.is_empty()
+                || attributes.languages.iter().any(|name| name == symbol))
+            && (attributes.platforms.is_empty()
+                || attributes
+                    .platforms
+                    .iter()
+                    .any(|platform| platform == std::env::consts::OS))
+    }
+}
+
+/// Parses a corpus file in the format of the `tree-sitter` CLI: each test starts with a header of
+/// its name between two lines of `=`, followed by its input, a line of `-` and the expected tree.
+/// The lines of the first header may end in a suffix, e.g. `===|`, which every other header and
+/// divider of the file then ends in as well, e.g. `---|`, so inputs can contain lines of `=` and
+/// `-` themselves. Of several dividers the longest one counts.
+pub fn parse_corpus(source: &str) -> Vec<CorpusTest> {
+    let suffix = |header: &Captures, name| {
+        header
+            .name(name)
+            .map(|suffix| suffix.as_str().trim().to_string())
+    };
+    let first_suffix = HEADER_REGEX
+        .captures(source)
+        .and_then(|header| suffix(&header, "suffix1"));
+    let headers: Vec<_> = HEADER_REGEX
+        .captures_iter(source)
+        .filter(|header| {
+            suffix(header, "suffix1") == first_suffix && suffix(header, "suffix2") == first_suffix
+        })
+        .collect();
+
+    let mut tests = Vec::new();
+    for (index, header) in headers.iter().enumerate() {
+        let whole = header.get(0).unwrap();
+        let body_end = headers
+            .get(index + 1)
+            .map_or(source.len(), |next| next.get(0).unwrap().start());
+        let body = &source[whole.end()..body_end];
+
+        let Some(divider) = DIVIDER_REGEX
+            .captures_iter(body)
+            .filter(|divider| suffix(divider, "suffix") == first_suffix)
+            .max_by_key(|divider| divider["hyphens"].len())
+            .map(|divider| divider.get(0).unwrap())
+        else {
+            continue;
+        };
+
+        let mut name = Vec::new();
+        let mut attributes = CorpusAttributes::default();
+        for line in header["name"].lines() {
+            let line = line.trim();
+            match line {
+                ":skip" => attributes.skip = true,
+                ":error" => attributes.error = true,
+                ":fail-fast" => attributes.fail_fast = true,
+                ":cst" => attributes.cst = true,
+                _ => {
+                    let argument = |attribute: &str| {
+                        line.strip_prefix(attribute)?
+                            .strip_suffix(')')
+                            .map(|argument| argument.trim().to_string())
+                    };
+                    if let Some(language) = argument(":language(") {
+                        attributes.languages.push(language);
+                    } else if let Some(platform) = argument(":platform(") {
+                        attributes.platforms.push(platform);
+                    } else if !line.starts_with(':') && !line.is_empty() {
+                        name.push(line);
+                    }
+                }
+            }
+        }
+
+        // the line ending before the divider is not part of the input
+        let input = &body[..divider.start()];
+        let input = input
+            .strip_suffix('\n')
+            .map_or(input, |input| input.strip_suffix('\r').unwrap_or(input));
+        let expected = &body[divider.end()..];
+        tests.push(CorpusTest {
+            name: name.join(" "),
+            line: source[..whole.start()].lines().count() + 1,
+            input: input.to_string(),
+            expected: match attributes.cst {
+                true => expected.trim().to_string(),
+                false => normalize_sexp(expected),
+            },
+            attributes,
+        });
+    }
+    tests
+}
+
+/// Reads the tests of every corpus file in a directory and its subdirectories, in file name
+/// order, with the path of their file.
+pub fn read_corpus(directory: &Path) -> Result<Vec<(String, Vec<CorpusTest>)>, String> {
+    fn collect(directory: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<(), String> {
+        let entries = fs::read_dir(directory)
+            .map_err(|e| format!("Failed to read {}: {}", directory.display(), e))?;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                collect(&path, files)?;
+            } else if !entry.file_name().to_string_lossy().starts_with('.') {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    collect(directory, &mut files)?;
+    files.sort();
+    files
+        .into_iter()
+        .map(|path| {
+            let source = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let relative = path.strip_prefix(directory).unwrap_or(&path);
+            Ok((relative.display().to_string(), parse_corpus(&source)))
+        })
+        .collect()
+}
+
+/// Normalizes an S-expression the way the `tree-sitter` CLI compares them: without `;` comments
+/// and with every run of whitespace collapsed to a single space.
+pub fn normalize_sexp(sexp: &str) -> String {
+    let sexp = COMMENT_REGEX.replace_all(sexp, "");
+    WHITESPACE_REGEX
+        .replace_all(sexp.trim(), " ")
+        .replace(" )", ")")
+}
+
+/// Whether an S-expression names the fields of nodes, e.g. `(call function: (identifier))`.
+pub fn has_fields(sexp: &str) -> bool {
+    FIELD_REGEX.is_match(sexp)
+}
+
+/// Removes the field names of an S-expression, for comparing it with an expected tree that
+/// leaves them out.
+pub fn strip_fields(sexp: &str) -> String {
+    FIELD_REGEX.replace_all(sexp, " (").to_string()
+}
+
+/// Formats a normalized S-expression with one node per line, indented by its depth.
+pub fn format_sexp(sexp: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut rest = sexp;
+    while let Some(c) = rest.chars().next() {
+        let length = match c {
+            '(' | ')' => 1,
+            c if c.is_whitespace() => {
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            // anonymous node names of `MISSING` nodes, which may contain anything
+            '"' => {
+                let mut escaped = false;
+                rest[1..]
+                    .find(|c| {
+                        let end = c == '"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        end
+                    })
+                    .map_or(rest.len(), |end| end + 2)
+            }
+            _ => rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(rest.len()),
+        };
+        tokens.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+
+    let mut formatted = String::new();
+    let mut depth = 0usize;
+    let mut after_field = false;
+    let mut after_open = false;
+    for token in tokens {
+        match token {
+            "(" => {
+                if !after_field && !formatted.is_empty() {
+                    let _ = write!(formatted, "\n{}", "  ".repeat(depth));
+                }
+                formatted.push('(');
+                depth += 1;
+            }
+            ")" => {
+                formatted.push(')');
+                depth = depth.saturating_sub(1);
+            }
+            field if field.ends_with(':') => {
+                let _ = write!(formatted, "\n{}{} ", "  ".repeat(depth), field);
+            }
+            atom if after_open => formatted.push_str(atom),
+            atom => {
+                formatted.push(' ');
+                formatted.push_str(atom);
+            }
+        }
+        after_field = token.ends_with(':');
+        after_open = token == "(";
+    }
+    formatted
+}
+
+/// Compares two formatted trees line by line, prefixing lines only the expected tree has with
+/// `-`, lines only the actual tree has with `+`, and common lines with a space.
+pub fn tree_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // lengths of the longest common subsequences of the remaining lines
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            let _ = writeln!(diff, "  {}", expected[i]);
+            i += 1;
+            j += 1;
+        } else if i < expected.len()
+            && (j == actual.len() || lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            let _ = writeln!(diff, "- {}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(diff, "+ {}", actual[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tests() {
+        let tests = parse_corpus(
+            "==================\nReturn statement\n==================\n\nreturn 1;\n\n---\n\n(program\n  ; a comment\n  (return_statement (number)))\n\n=====\nEmpty\n=====\n---\n(program)\n",
+        );
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "Return statement");
+        assert_eq!(tests[0].line, 1);
+        assert_eq!(tests[0].input, "\nreturn 1;\n");
+        assert_eq!(tests[0].expected, "(program (return_statement (number)))");
+        assert_eq!(tests[1].name, "Empty");
+        assert_eq!(tests[1].line, 13);
+        assert_eq!(tests[1].input, "");
+        assert_eq!(tests[1].expected, "(program)");
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let tests = parse_corpus(
+            "===\nSkipped\n:skip\n===\na\n---\n(a)\n\n===\nInvalid\n:error\n:fail-fast\n===\nb\n---\n\n===\nTSX only\n:language(tsx)\n:platform(linux)\n:cst\n===\nc\n---\n\n0:0 - 1:0   program\n",
+        );
+        assert_eq!(tests.len(), 3);
+        assert!(tests[0].attributes.skip);
+        assert!(!tests[0].applies_to("typescript"));
+        assert!(tests[1].attributes.error && tests[1].attributes.fail_fast);
+        assert_eq!(tests[1].expected, "");
+
+        let attributes = &tests[2].attributes;
+        assert_eq!(tests[2].name, "TSX only");
+        assert_eq!(attributes.languages, vec!["tsx"]);
+        assert_eq!(attributes.platforms, vec!["linux"]);
+        assert!(attributes.cst);
+        assert_eq!(tests[2].expected, "0:0 - 1:0   program");
+        assert!(!tests[2].applies_to("typescript"));
+    }
+
+    #[test]
+    fn uses_the_longest_divider() {
+        let tests =
+            parse_corpus("===\nFront matter\n===\n---\ntitle: a\n---\n------\n(document)\n");
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].input, "---\ntitle: a\n---");
+        assert_eq!(tests[0].expected, "(document)");
+    }
+
+    #[test]
+    fn matches_suffixed_headers_and_dividers() {
+        let tests = parse_corpus(
+            "===|\nFirst\n===|\n===\nnot a header\n===\n---\n---|\n(a)\n\n===|\nSecond\n===|\nb\n---|\n(b)\n",
+        );
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "First");
+        assert_eq!(tests[0].input, "===\nnot a header\n===\n---");
+        assert_eq!(tests[0].expected, "(a)");
+        assert_eq!(tests[1].name, "Second");
+        assert_eq!(tests[1].line, 11);
+        assert_eq!(tests[1].expected, "(b)");
+    }
+
+    #[test]
+    fn strips_fields() {
+        let sexp = "(call function: (identifier) arguments: (argument_list (string)))";
+        assert!(has_fields(sexp));
+        assert_eq!(
+            strip_fields(sexp),
+            "(call (identifier) (argument_list (string)))"
+        );
+        assert!(!has_fields(&strip_fields(sexp)));
+    }
+
+    #[test]
+    fn formats_and_diffs_trees() {
+        let expected = format_sexp("(call function: (identifier) (MISSING \")\"))");
+        assert_eq!(
+            expected,
+            "(call\n  function: (identifier)\n  (MISSING \")\"))"
+        );
+
+        let actual = format_sexp("(call function: (member_expression) (MISSING \")\"))");
+        assert_eq!(
+            tree_diff(&expected, &actual),
+            "  (call\n-   function: (identifier)\n+   function: (member_expression)\n    (MISSING \")\"))\n"
+        );
+    }
+}
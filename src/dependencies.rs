@@ -0,0 +1,255 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use crate::Language;
+
+/// Groups languages into levels, where every language only depends on languages from earlier
+/// levels. Languages within a level are independent of each other and can be processed in
+/// parallel.
+pub fn dependency_levels(
+    languages: &BTreeMap<String, Language>,
+) -> Result<Vec<Vec<String>>, String> {
+    for (key, language) in languages {
+        for dependency in &language.dependencies {
+            if !languages.contains_key(dependency) {
+                return Err(format!(
+                    "Language '{}' depends on unknown language '{}'",
+                    key, dependency
+                ));
+            }
+        }
+    }
+
+    levels_or_unresolved(languages).map_err(|unresolved| {
+        format!(
+            "Dependency cycle between languages: {}",
+            unresolved.join(", ")
+        )
+    })
+}
+
+/// Returns the languages that are part of, or depend on, a dependency cycle. Dependencies on
+/// unknown languages are ignored.
+pub fn dependency_cycle(languages: &BTreeMap<String, Language>) -> Vec<String> {
+    levels_or_unresolved(languages).err().unwrap_or_default()
+}
+
+fn levels_or_unresolved(
+    languages: &BTreeMap<String, Language>,
+) -> Result<Vec<Vec<String>>, Vec<String>> {
+    let mut remaining: BTreeSet<&String> = languages.keys().collect();
+    let mut levels = Vec::new();
+    while !remaining.is_empty() {
+        let level: Vec<String> = remaining
+            .iter()
+            .filter(|key| {
+                languages[key.as_str()]
+                    .dependencies
+                    .iter()
+                    .all(|dependency| !remaining.contains(dependency))
+            })
+            .map(|key| key.to_string())
+            .collect();
+
+        if level.is_empty() {
+            return Err(remaining.iter().map(|key| key.to_string()).collect());
+        }
+        for key in &level {
+            remaining.remove(key);
+        }
+        levels.push(level);
+    }
+    Ok(levels)
+}
+
+/// Returns the language itself along with everything it transitively depends on, in
+/// dependency order.
+pub fn dependency_closure(
+    language_name: &str,
+    languages: &BTreeMap<String, Language>,
+) -> Result<Vec<String>, String> {
+    let mut closure = BTreeSet::new();
+    let mut pending = vec![language_name.to_string()];
+    while let Some(key) = pending.pop() {
+        let language = languages
+            .get(&key)
+            .ok_or_else(|| format!("Unknown language '{}'", key))?;
+        if closure.insert(key) {
+            pending.extend(language.dependencies.iter().cloned());
+        }
+    }
+
+    let subset: BTreeMap<String, Language> = languages
+        .iter()
+        .filter(|(key, _)| closure.contains(*key))
+        .map(|(key, language)| (key.clone(), language.clone()))
+        .collect();
+    Ok(dependency_levels(&subset)?.into_iter().flatten().collect())
+}
+
+/// Links the vendored copies of a language's dependencies into its `node_modules` directory,
+/// so that `require('tree-sitter-<dependency>/grammar')` in `grammar.js` resolves without npm.
+pub fn provision_node_modules(
+    language: &Language,
+    languages: &BTreeMap<String, Language>,
+    directory: &Path,
+) -> io::Result<()> {
+    if language.dependencies.is_empty() {
+        return Ok(());
+    }
+
+    let node_modules = directory.join(&language.name).join("node_modules");
+    fs::create_dir_all(&node_modules)?;
+
+    for dependency_name in &language.dependencies {
+        let dependency = &languages[dependency_name];
+        let source = directory.join(&dependency.name);
+        if !source.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Dependency '{}' of '{}' has not been fetched to {}",
+                    dependency_name,
+                    language.name,
+                    source.display()
+                ),
+            ));
+        }
+
+        let link = node_modules.join(&dependency.name);
+        if fs::symlink_metadata(&link).is_ok() {
+            fs::remove_file(&link).or_else(|_| fs::remove_dir_all(&link))?;
+        }
+        symlink_dir(&fs::canonicalize(&source)?, &link)?;
+    }
+    Ok(())
+}
+
+static REQUIRE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\brequire\s*\(\s*['"]([^'"]+)['"]\s*\)"#).unwrap());
+
+/// Node.js modules `tree-sitter generate` provides without a `node_modules` directory.
+const NODE_BUILTINS: [&str; 6] = ["assert", "fs", "os", "path", "process", "util"];
+
+/// Returns the modules `grammar.js` in a grammar directory, or the scripts it requires in turn,
+/// `require()` that cannot be resolved, such as an npm package like `regexp-util` that is not a
+/// vendored grammar linked into `node_modules`.
+pub fn unresolved_requires(grammar_directory: &Path) -> Vec<String> {
+    let mut unresolved = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![grammar_directory.join("grammar.js")];
+    while let Some(script) = pending.pop() {
+        if !visited.insert(script.clone()) {
+            continue;
+        }
+        let Ok(contents) = fs::read_to_string(&script) else {
+            continue;
+        };
+        let script_directory = script.parent().unwrap_or(grammar_directory);
+        let code = contents
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        for captures in REQUIRE_REGEX.captures_iter(&code) {
+            let module = &captures[1];
+            if module.starts_with("./") || module.starts_with("../") {
+                match resolve_script(&script_directory.join(module)) {
+                    Some(path) if path.extension().is_some_and(|extension| extension == "js") => {
+                        pending.push(path)
+                    }
+                    Some(_) => (),
+                    None => unresolved.push(module.to_string()),
+                }
+            } else if !is_node_module(script_directory, module) {
+                unresolved.push(module.to_string());
+            }
+        }
+    }
+    unresolved.sort();
+    unresolved.dedup();
+    unresolved
+}
+
+/// Resolves a relative `require()` the way Node.js does, trying the `.js` and `.json` extensions
+/// and `index.js`.
+fn resolve_script(path: &Path) -> Option<PathBuf> {
+    let mut candidates = vec![path.to_path_buf()];
+    for extension in ["js", "json"] {
+        let mut file_name = path.file_name()?.to_os_string();
+        file_name.push(format!(".{}", extension));
+        candidates.push(path.with_file_name(file_name));
+    }
+    candidates.push(path.join("index.js"));
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+/// Whether a module is built into Node.js or installed in a `node_modules` directory next to the
+/// script or one of its ancestors.
+fn is_node_module(script_directory: &Path, module: &str) -> bool {
+    let module = module.strip_prefix("node:").unwrap_or(module);
+    // `tree-sitter-c/grammar` is provided by the package `tree-sitter-c`, `@scope/name/x` by
+    // `@scope/name`
+    let segments = if module.starts_with('@') { 2 } else { 1 };
+    let package: PathBuf = module.split('/').take(segments).collect();
+    NODE_BUILTINS.contains(&module)
+        || script_directory
+            .ancestors()
+            .any(|directory| directory.join("node_modules").join(&package).exists())
+}
+
+#[cfg(unix)]
+fn symlink_dir(source: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(source: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(source, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_requires_that_cannot_be_resolved() {
+        let root = std::env::temp_dir().join(format!("requires-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files = [
+            (
+                "grammar.js",
+                "const C = require('tree-sitter-c/grammar');\n\
+                 const util = require(\"regexp-util\");\n\
+                 // const old = require('./old');\n\
+                 const rules = require('./grammar/rules');\n\
+                 const path = require('node:path');\n",
+            ),
+            (
+                "grammar/rules.js",
+                "module.exports = { ...require('../common'), ...require('./missing') };",
+            ),
+            ("common/index.js", "module.exports = {};"),
+            (
+                "node_modules/tree-sitter-c/grammar.js",
+                "module.exports = {};",
+            ),
+        ];
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let unresolved = unresolved_requires(&root);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(unresolved, ["./missing", "regexp-util"]);
+    }
+}
@@ -0,0 +1,52 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Returns the hex encoded SHA-256 digest of some bytes.
+pub fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the hex encoded SHA-256 digest of a file's contents.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    Ok(sha256(&fs::read(path)?))
+}
+
+/// Returns a SHA-256 digest covering the paths and contents of every regular file in a directory
+/// tree. Files are visited in path order and symbolic links are skipped, so the digest only
+/// changes when the vendored files themselves change.
+pub fn tree_sha256(directory: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(directory, directory, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for relative_path in files {
+        let digest = file_sha256(&directory.join(&relative_path))?;
+        hasher.update(relative_path.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(digest.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn collect_files(root: &Path, directory: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &entry.path(), files)?;
+        } else if file_type.is_file() {
+            let path = entry.path();
+            let relative_path = path
+                .strip_prefix(root)
+                .expect("Path is inside the directory")
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.push(relative_path);
+        }
+    }
+    Ok(())
+}
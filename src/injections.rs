@@ -0,0 +1,193 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::metadata::grammar_query_files;
+use crate::metadata::grammar_units;
+use crate::metadata::read_grammar_metadata;
+use crate::metadata::resolve_query_path;
+use crate::query::parse_query;
+use crate::query::PatternKind;
+use crate::query::PredicateArgument;
+use crate::Language;
+
+/// Captures with a meaning in injection queries, which older queries otherwise use to name the
+/// injected language, e.g. `(comment) @jsdoc`.
+const INJECTION_CAPTURES: [&str; 4] = [
+    "injection.content",
+    "injection.language",
+    "content",
+    "language",
+];
+
+/// The languages a grammar's injection queries inject.
+#[derive(Debug, Default)]
+pub struct Injections {
+    /// Names of injected languages given in the queries
+    pub names: BTreeSet<String>,
+    /// Whether the queries also inject languages named by the document, e.g. the info string of a
+    /// fenced code block in markdown
+    pub dynamic: bool,
+}
+
+/// Which languages the vendored grammars inject into each other, keyed by language.
+#[derive(Debug, Default)]
+pub struct InjectionGraph {
+    /// Languages each language injects, by their key in the languages file
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+    /// Languages whose queries inject languages named by the document
+    pub dynamic: BTreeSet<String>,
+    /// Injected names no vendored language is known by, with the languages injecting them
+    pub not_vendored: BTreeMap<String, BTreeSet<String>>,
+    /// Injected names of vendored languages that no `injection-regex` matches, with the language
+    /// they name and the languages injecting them
+    pub unmatched: BTreeMap<String, (String, BTreeSet<String>)>,
+    /// Injection queries and `injection-regex` values that could not be parsed
+    pub errors: Vec<String>,
+}
+
+/// Reads the names of the languages injected by the injection queries of a grammar, both its own
+/// and the ones of other grammars its metadata lists: the `injection.language` set by `#set!`,
+/// and captures named after a language in older queries.
+pub fn grammar_injections(
+    language: &Language,
+    grammar_directory: &Path,
+) -> Result<Injections, String> {
+    let mut injections = Injections::default();
+    let mut paths: Vec<PathBuf> = grammar_query_files(language, grammar_directory)
+        .into_iter()
+        .filter(|file| {
+            Path::new(file)
+                .file_stem()
+                .is_some_and(|stem| stem.to_string_lossy().contains("injections"))
+        })
+        .map(|file| grammar_directory.join(file))
+        .collect();
+    for grammar in read_grammar_metadata(grammar_directory) {
+        for file in grammar.queries("injections") {
+            if let Some(path) = resolve_query_path(grammar_directory, file) {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+
+    for path in paths {
+        let source = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let patterns = parse_query(&source)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        for pattern in &patterns {
+            pattern.walk(&mut |pattern| {
+                for capture in &pattern.captures {
+                    if capture.text == "injection.language" || capture.text == "language" {
+                        injections.dynamic = true;
+                    } else if !INJECTION_CAPTURES.contains(&capture.text.as_str())
+                        && !capture.text.starts_with('_')
+                    {
+                        injections.names.insert(capture.text.clone());
+                    }
+                }
+                let PatternKind::Predicate(predicate) = &pattern.kind else {
+                    return;
+                };
+                // the directive of neovim's markdown queries for fenced code blocks
+                if predicate.name.text == "set-lang-from-info-string!" {
+                    injections.dynamic = true;
+                }
+                if predicate.name.text != "set!" {
+                    return;
+                }
+                if let [key, value] = predicate.arguments.as_slice() {
+                    let is_language = matches!(key, PredicateArgument::Identifier(key)
+                        | PredicateArgument::String(key) if key.text == "injection.language");
+                    if is_language && !matches!(value, PredicateArgument::Capture(_)) {
+                        injections.names.insert(value.name().text.clone());
+                    }
+                }
+            });
+        }
+    }
+    Ok(injections)
+}
+
+/// Builds the graph of injections between the vendored grammars, resolving injected names by the
+/// `injection-regex` of each grammar like the tree-sitter CLI does.
+pub fn injection_graph(languages: &BTreeMap<String, Language>, directory: &Path) -> InjectionGraph {
+    let mut graph = InjectionGraph::default();
+    let mut regexes: Vec<(Regex, &String)> = Vec::new();
+    // the names a vendored language is known by, including the grammars of repositories with
+    // several, like `markdown_inline`
+    let mut known_names: BTreeMap<String, &String> = BTreeMap::new();
+    for (language_name, language) in languages {
+        let grammar_directory = directory.join(&language.name);
+        known_names.insert(language_name.clone(), language_name);
+        for alias in &language.aliases {
+            known_names.insert(alias.clone(), language_name);
+        }
+        for unit in grammar_units(language, &grammar_directory) {
+            known_names.entry(unit.symbol).or_insert(language_name);
+        }
+        for grammar in read_grammar_metadata(&grammar_directory) {
+            let Some(injection_regex) = &grammar.injection_regex else {
+                continue;
+            };
+            match Regex::new(injection_regex) {
+                Ok(regex) => regexes.push((regex, language_name)),
+                Err(e) => graph.errors.push(format!(
+                    "Invalid injection-regex of {}: {}: {}",
+                    language.name, injection_regex, e
+                )),
+            }
+        }
+    }
+
+    for (language_name, language) in languages {
+        let grammar_directory = directory.join(&language.name);
+        if !grammar_directory.is_dir() {
+            continue;
+        }
+        let injections = match grammar_injections(language, &grammar_directory) {
+            Ok(injections) => injections,
+            Err(e) => {
+                graph.errors.push(e);
+                continue;
+            }
+        };
+        if injections.dynamic {
+            graph.dynamic.insert(language_name.clone());
+        }
+        let edges = graph.edges.entry(language_name.clone()).or_default();
+        for name in injections.names {
+            if let Some((_, target)) = regexes.iter().find(|(regex, _)| regex.is_match(&name)) {
+                edges.insert((*target).clone());
+                continue;
+            }
+            match known_names.get(&name) {
+                Some(key) => {
+                    graph
+                        .unmatched
+                        .entry(name)
+                        .or_insert_with(|| ((*key).clone(), BTreeSet::new()))
+                        .1
+                        .insert(language_name.clone());
+                }
+                None => {
+                    graph
+                        .not_vendored
+                        .entry(name)
+                        .or_default()
+                        .insert(language_name.clone());
+                }
+            }
+        }
+        if edges.is_empty() && !injections.dynamic {
+            graph.edges.remove(language_name);
+        }
+    }
+    graph
+}
@@ -1,37 +1,235 @@
 use futures::future::join_all;
-use git2::Oid;
 use git2::Repository;
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
+
+pub mod abi;
+pub mod bench;
+pub mod build;
+pub mod bundle;
+pub mod captures;
+pub mod check;
+pub mod codegen;
+pub mod corpus;
+pub mod dependencies;
+pub mod hashing;
+pub mod injections;
+pub mod licenses;
+pub mod lint;
+pub mod lockfile;
+pub mod metadata;
+pub mod native;
+pub mod node_types;
+pub mod patches;
+pub mod prune;
+pub mod query;
+pub mod runtime;
+pub mod rust_crate;
+pub mod sbom;
+pub mod snapshot;
+pub mod testing;
+pub mod validate;
+
+use abi::grammar_abi_versions;
+use abi::AbiRange;
+use bench::bench_grammar;
+use bench::corpus_inputs;
+use bench::directory_inputs;
+use bench::BenchResult;
+use build::build_key;
+use build::build_wasm;
+use build::detect_wasm_compiler;
+use build::wasm_directory;
+use build::BuildCache;
+use build::BuiltArtifact;
+use build::WasmToolchain;
+use bundle::create_bundle;
+use bundle::write_bundle;
+use captures::is_private_capture;
+use captures::query_captures;
+use captures::rewrite_captures;
+use captures::CaptureVocabulary;
+use check::parse_languages_file;
+use check::read_languages_file;
+use check::LoadError;
+use codegen::generate_rust_ast;
+use dependencies::dependency_closure;
+use dependencies::dependency_levels;
+use dependencies::provision_node_modules;
+use dependencies::unresolved_requires;
+use injections::injection_graph;
+use licenses::audit_licenses;
+use licenses::third_party_notices;
+use licenses::LicensePolicy;
+use licenses::LicenseStatus;
+use lint::lint_grammar_queries;
+use lockfile::grammar_commit;
+use lockfile::read_lockfile;
+use lockfile::update_lockfile;
+use lockfile::LockedLanguage;
+use lockfile::Lockfile;
+use metadata::grammar_query_files;
+use metadata::grammar_units;
+use metadata::language_grammar_unit;
+use metadata::read_query;
+use metadata::resolve_query_path;
+use metadata::root_grammar_metadata;
+use metadata::select_query_flavor;
+use metadata::GrammarUnit;
+use metadata::FLAVORED_QUERIES;
+use native::build_native_object;
+use native::create_archive;
+use native::detect_native_toolchain;
+use native::grammars_header;
+use native::HEADER_NAME;
+use native::STATIC_LIBRARY_NAME;
+use node_types::node_types_index;
+use node_types::read_node_types;
+use patches::apply_patches;
+use patches::checkout_pinned_commit;
+use patches::language_patches;
+use patches::patch_diff;
+use prune::format_size;
+use prune::prune_grammar;
+use prune::Prune;
+use prune::PruneReport;
+use runtime::load_grammar;
+use runtime::parsers_directory;
+use rust_crate::generate_rust_crate;
+use rust_crate::grammars_workspace;
+use rust_crate::write_grammar_bindings;
+use rust_crate::TreeSitterVersion;
+use sbom::cyclonedx_sbom;
+use snapshot::language_samples;
+use snapshot::snapshot_samples;
+use snapshot::SnapshotStatus;
+use testing::grammar_test_suites;
+use testing::run_corpus;
+use testing::run_tree_sitter_test;
+use testing::test_suites;
+use validate::artifact_file_names;
+use validate::validate_wasm_grammars;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Language {
     pub name: String,
     pub git: String,
     pub hash: Option<String>,
+    /// Keys of the languages whose grammars this grammar `require()`s, e.g. `["c"]` for cpp
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// Other names the language is known by, e.g. `["sh", "shell"]` for bash
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Patches applied in order after fetching, relative to the languages file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
+    /// Globs of files to keep after fetching, in addition to the global `[prune]` ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Globs of files to remove after fetching, in addition to the global `[prune]` ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Variant of the queries to ship, e.g. `helix` for `queries/helix-highlights.scm`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_flavor: Option<String>,
+    /// Directory of sample files to snapshot the parse trees of, relative to the languages file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<String>,
 }
 
 impl Language {
     pub fn new(name: String, git: String, hash: Option<String>) -> Self {
-        Self { name, git, hash }
+        Self {
+            name,
+            git,
+            hash,
+            dependencies: Vec::new(),
+            aliases: Vec::new(),
+            patches: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            query_flavor: None,
+            samples: None,
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct LanguageGrammarsTOML {
+    #[serde(default, skip_serializing_if = "Prune::is_default")]
+    prune: Prune,
+    #[serde(default, skip_serializing_if = "LicensePolicy::is_default")]
+    licenses: LicensePolicy,
+    #[serde(default, skip_serializing_if = "AbiRange::is_default")]
+    abi: AbiRange,
+    #[serde(default, skip_serializing_if = "CaptureVocabulary::is_default")]
+    captures: CaptureVocabulary,
     languages: BTreeMap<String, Language>,
 }
 
-pub fn add_language_grammar_to_toml(name: String, language: Language, file_path: PathBuf) {
-    let toml_contents = fs::read_to_string(&file_path).expect("Failed to read TOML file");
-    let mut languages: LanguageGrammarsTOML =
-        toml::from_str(&toml_contents).expect("Failed to parse TOML");
+fn read_languages_toml(file_path: &Path) -> Result<LanguageGrammarsTOML, LoadError> {
+    parse_languages_file(file_path, &read_languages_file(file_path)?)
+}
+
+/// Reads all languages listed in the languages file, keyed by language name.
+pub fn read_languages(file_path: &Path) -> Result<BTreeMap<String, Language>, LoadError> {
+    Ok(read_languages_toml(file_path)?.languages)
+}
+
+/// Reads the languages file for a command, reporting why it could not be loaded.
+fn load_languages_toml(file_path: &Path) -> Option<LanguageGrammarsTOML> {
+    read_languages_toml(file_path)
+        .inspect_err(|e| eprintln!("Error: {}", e))
+        .ok()
+}
+
+fn load_languages(file_path: &Path) -> Option<BTreeMap<String, Language>> {
+    load_languages_toml(file_path).map(|languages| languages.languages)
+}
+
+/// Reads the lockfile of the languages file for a command, reporting why it could not be loaded.
+fn load_lockfile(file_path: &Path) -> Option<Lockfile> {
+    read_lockfile(file_path)
+        .inspect_err(|e| eprintln!("Error: {}", e))
+        .ok()
+}
+
+/// Selects the language named `name`, or every language with `all`, reporting a language missing
+/// from the languages file or that neither was given.
+fn select_languages(
+    languages: &BTreeMap<String, Language>,
+    name: Option<String>,
+    all: bool,
+) -> Option<Vec<(String, Language)>> {
+    match (name, all) {
+        (Some(name), _) => match languages.get(&name) {
+            Some(language) => Some(vec![(name, language.clone())]),
+            None => {
+                eprintln!("Language {} not found in the languages file", name);
+                None
+            }
+        },
+        (None, true) => Some(languages.clone().into_iter().collect()),
+        (None, false) => {
+            eprintln!("Please provide a language name or use the --all option.");
+            None
+        }
+    }
+}
+
+pub fn add_language_grammar_to_toml(name: String, language: Language, file_path: PathBuf) -> bool {
+    let Some(mut languages) = load_languages_toml(&file_path) else {
+        return false;
+    };
 
     if let Some(existing_language) = languages.languages.get_mut(&name) {
         if existing_language.hash != language.hash {
@@ -40,6 +238,12 @@ pub fn add_language_grammar_to_toml(name: String, language: Language, file_path:
         if existing_language.git != language.git {
             existing_language.git = language.git;
         }
+        if !language.dependencies.is_empty() {
+            existing_language.dependencies = language.dependencies;
+        }
+        if !language.aliases.is_empty() {
+            existing_language.aliases = language.aliases;
+        }
     } else {
         languages.languages.insert(name, language);
     }
@@ -53,114 +257,424 @@ pub fn add_language_grammar_to_toml(name: String, language: Language, file_path:
         toml::to_string_pretty(&languages).expect("Failed to serialize to TOML")
     );
     fs::write(&file_path, updated_toml).expect("Failed to write updated TOML file");
+    true
+}
+
+/// Steps performed on grammars after fetching them.
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Compile grammars to WebAssembly
+    pub wasm: bool,
+    /// Regenerate parsers from `grammar.js`
+    pub generate: bool,
+    /// Rebuild grammars even if their sources are unchanged
+    pub force: bool,
+    /// Toolchain to compile grammars to WebAssembly with, detected if not set
+    pub wasm_toolchain: Option<WasmToolchain>,
 }
 
 pub async fn update_language(
     name: Option<String>,
     all: bool,
-    wasm: bool,
+    options: FetchOptions,
     file_path: PathBuf,
     directory: PathBuf,
-) -> () {
-    if let Some(language_name) = name {
-        let toml_contents = fs::read_to_string(&file_path).expect("Failed to read TOML file");
-        let languages: LanguageGrammarsTOML =
-            toml::from_str(&toml_contents).expect("Failed to parse TOML");
+) -> bool {
+    let Some(LanguageGrammarsTOML {
+        prune,
+        abi,
+        languages,
+        ..
+    }) = load_languages_toml(&file_path)
+    else {
+        return false;
+    };
+    // fail before cloning anything that could not be recorded
+    if load_lockfile(&file_path).is_none() {
+        return false;
+    }
 
-        if let Some(language) = languages.languages.get(&language_name) {
+    if let Some(language_name) = name {
+        if let Some(language) = languages.get(&language_name) {
             let destination_directory = format!("{}{}", directory.display(), &language.name);
-            clone_repository(language.clone(), destination_directory.clone()).await;
+            let patches = language_patches(&language_name, language, &file_path);
+            let prune_rules = prune.rules_for(language);
+            let Some(fetched) = clone_repository(
+                language.clone(),
+                destination_directory.clone(),
+                patches,
+                prune_rules,
+            )
+            .await
+            else {
+                return false;
+            };
+            if let Some(report) = fetched.pruned.filter(|report| report.files > 0) {
+                println!("Removed {} from {}", report, language.name);
+            }
+            if let Err(e) = update_lockfile(
+                &file_path,
+                vec![(language_name.clone(), fetched.locked(language))],
+            ) {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+
+            // dependencies may `require()` grammars of their own
+            let provisioned = dependency_closure(&language_name, &languages).and_then(|closure| {
+                closure.iter().try_for_each(|key| {
+                    provision_node_modules(&languages[key], &languages, &directory)
+                        .map_err(|e| e.to_string())
+                })
+            });
+            if let Err(e) = provisioned {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+
+            if options.generate {
+                if let Err(e) =
+                    generate_grammar(language.clone(), destination_directory.clone(), None).await
+                {
+                    eprintln!("Error: {}", e);
+                    return false;
+                }
+            }
 
             // compiling to wasm is enabled
-            if wasm {
-                let target = format!("../../{}{}.wasm", "wasm/", language_name);
-                Command::new("tree-sitter")
-                    .current_dir(destination_directory.clone())
-                    .arg("build")
-                    .arg("--wasm")
-                    .arg("-o")
-                    .arg(target)
-                    .spawn()
-                    .expect("Failed to compile grammar to WebAssembly");
+            if options.wasm {
+                let artifact = format!("{}.wasm", language_name);
+                return build_grammars_to_wasm(
+                    vec![(language.clone(), destination_directory.clone(), artifact)],
+                    &directory,
+                    &options,
+                    &abi,
+                )
+                .await;
             }
         } else {
             eprintln!("Language not found: {}", language_name);
+            return false;
         }
     } else if all {
         println!("Updating all languages");
-        let toml_contents = fs::read_to_string(&file_path).expect("Failed to read TOML file");
-        let languages: LanguageGrammarsTOML =
-            toml::from_str(&toml_contents).expect("Failed to parse TOML");
 
+        // grammars are generated and built after the grammars they `require()`
+        let levels = match dependency_levels(&languages) {
+            Ok(levels) => levels,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+        };
+
+        let mut passed = true;
         let grammars_to_update: Vec<_> = languages
-            .languages
             .iter()
-            .map(|(_, language)| {
+            .map(|(language_name, language)| {
                 let destination_directory = format!("{}{}", directory.display(), &language.name);
-                (language.clone(), destination_directory)
+                let patches = language_patches(language_name, language, &file_path);
+                let prune_rules = prune.rules_for(language);
+                (
+                    language_name.clone(),
+                    language.clone(),
+                    destination_directory,
+                    patches,
+                    prune_rules,
+                )
             })
             .collect();
 
         let async_clones: Vec<_> = grammars_to_update
-            .clone()
             .into_iter()
-            .map(|(language, destination_directory)| {
-                tokio::spawn(async move {
-                    clone_repository(language.clone(), destination_directory.clone()).await
-                })
-            })
+            .map(
+                |(language_name, language, destination_directory, patches, prune_rules)| {
+                    tokio::spawn(async move {
+                        let fetched = clone_repository(
+                            language.clone(),
+                            destination_directory.clone(),
+                            patches,
+                            prune_rules,
+                        )
+                        .await;
+                        (language_name, language, fetched)
+                    })
+                },
+            )
             .collect();
 
+        let mut total_report = PruneReport::default();
+        let mut locked_languages = Vec::new();
+        let mut failed = BTreeSet::new();
         for task in async_clones {
-            if let Err(err) = task.await {
-                eprintln!("Async task error: {:?}", err);
+            match task.await {
+                Ok((language_name, language, Some(fetched))) => {
+                    if let Some(report) = fetched.pruned.filter(|report| report.files > 0) {
+                        println!("Removed {} from {}", report, language.name);
+                        total_report += report;
+                    }
+                    locked_languages.push((language_name, fetched.locked(&language)));
+                }
+                Ok((language_name, _, None)) => {
+                    passed = false;
+                    failed.insert(language_name);
+                }
+                Err(err) => {
+                    eprintln!("Async task error: {:?}", err);
+                    passed = false;
+                }
             }
         }
+        println!("Removed {} in total", total_report);
+        if let Err(e) = update_lockfile(&file_path, locked_languages) {
+            eprintln!("Error: {}", e);
+            passed = false;
+        }
 
-        let compile_grammars_to_wasm: Vec<_> = grammars_to_update
-            .clone()
-            .into_iter()
-            .map(|(language, destination_directory)| {
-                let wasm_file_path = format!("../../{}{}.wasm", "wasm/", language.name);
-                tokio::spawn(async move {
-                    let status = tokio::process::Command::new("tree-sitter")
-                        .current_dir(destination_directory)
-                        .arg("build")
-                        .arg("--wasm")
-                        .arg("-o")
-                        .arg(wasm_file_path)
-                        .status()
-                        .await;
-                    match status {
-                        Ok(status) if status.success() => Ok(()),
-                        Ok(status) => Err(format!(
-                            "Command exited with status: {}, for language: {}",
-                            status, language.name
-                        )),
-                        Err(e) => Err(format!(
-                            "Failed to execute command: {}, for language: {}",
-                            e, language.name
-                        )),
+        for level in levels {
+            let grammars_in_level: Vec<_> = level
+                .iter()
+                .filter(|language_name| !failed.contains(*language_name))
+                .filter_map(|language_name| {
+                    let language = &languages[language_name];
+                    if let Err(e) = provision_node_modules(language, &languages, &directory) {
+                        passed = false;
+                        eprintln!("Error: {}", e);
+                        return None;
                     }
+                    let destination_directory =
+                        format!("{}{}", directory.display(), &language.name);
+                    Some((language.clone(), destination_directory))
                 })
-            })
-            .collect();
+                .collect();
 
-        let results = join_all(compile_grammars_to_wasm).await;
+            if options.generate {
+                let generate_grammars: Vec<_> = grammars_in_level
+                    .clone()
+                    .into_iter()
+                    .map(|(language, destination_directory)| {
+                        tokio::spawn(generate_grammar(language, destination_directory, None))
+                    })
+                    .collect();
 
-        for result in results {
-            match result {
-                Ok(Ok(())) => (),
-                Ok(Err(e)) => eprintln!("Error: {}", e),
-                Err(e) => eprintln!("Join error: {:?}", e),
+                if !report_task_results(join_all(generate_grammars).await) {
+                    passed = false;
+                }
             }
+
+            let compile_grammars_to_wasm: Vec<_> = grammars_in_level
+                .into_iter()
+                .map(|(language, destination_directory)| {
+                    let artifact = format!("{}.wasm", language.name);
+                    (language, destination_directory, artifact)
+                })
+                .collect();
+
+            if options.wasm
+                && !build_grammars_to_wasm(compile_grammars_to_wasm, &directory, &options, &abi)
+                    .await
+            {
+                passed = false;
+            }
+        }
+        if !failed.is_empty() {
+            let failed: Vec<String> = failed.into_iter().collect();
+            eprintln!("Failed to fetch {}", failed.join(", "));
+            return false;
         }
+        return passed;
     } else {
         eprintln!("Please provide a language name or use the --all option.");
+        return false;
+    }
+    true
+}
+
+/// Compiles grammars to WebAssembly in parallel, skipping grammars whose sources and toolchain
+/// are unchanged since their artifact was last built, unless `force` is set. Returns whether a
+/// toolchain was found and every artifact was built and passed validation.
+async fn build_grammars_to_wasm(
+    grammars: Vec<(Language, String, String)>,
+    directory: &Path,
+    options: &FetchOptions,
+    abi: &AbiRange,
+) -> bool {
+    let wasm_directory = wasm_directory(directory);
+    let compiler = match detect_wasm_compiler(options.wasm_toolchain).await {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+    let mut cache = BuildCache::read(&wasm_directory);
+    fs::create_dir_all(&wasm_directory).expect("Failed to create WebAssembly directory");
+
+    let mut passed = true;
+    let builds: Vec<_> = grammars
+        .into_iter()
+        .filter_map(|(language, destination_directory, artifact)| {
+            let build_key = match build_key(&language, Path::new(&destination_directory), &compiler)
+            {
+                Ok(build_key) => build_key,
+                Err(e) => {
+                    passed = false;
+                    eprintln!("Error: {}", e);
+                    return None;
+                }
+            };
+            if !options.force && cache.is_fresh(&wasm_directory, &artifact, &build_key) {
+                println!("Skipping {}, sources are unchanged", language.name);
+                return None;
+            }
+
+            let output = wasm_directory.join(&artifact);
+            let compiler = compiler.clone();
+            let abi = *abi;
+            Some(tokio::spawn(async move {
+                let grammar_directory = Path::new(&destination_directory);
+                let command = build_wasm(&compiler, &language, grammar_directory, &output).await?;
+                let units = grammar_units(&language, grammar_directory);
+                let abi_version = validate_wasm_grammars(&output, &units, &abi).map_err(|e| {
+                    // never ship an artifact that cannot be loaded
+                    let _ = fs::remove_file(&output);
+                    format!("Build of {} failed validation: {}", language.name, e)
+                })?;
+                let built = BuiltArtifact {
+                    key: build_key,
+                    toolchain: compiler.toolchain.to_string(),
+                    command,
+                };
+                Ok::<_, String>((artifact, abi_version, built))
+            }))
+        })
+        .collect();
+
+    for result in join_all(builds).await {
+        match result {
+            Ok(Ok((artifact, abi_version, built))) => {
+                println!(
+                    "Built {} (ABI version {}) with {}: {}",
+                    artifact, abi_version, built.toolchain, built.command
+                );
+                cache.artifacts.insert(artifact, built);
+            }
+            Ok(Err(e)) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+            Err(e) => {
+                passed = false;
+                eprintln!("Join error: {:?}", e);
+            }
+        }
+    }
+
+    cache.write(&wasm_directory);
+    passed
+}
+
+/// Runs `tree-sitter generate` for every grammar in the repository, which is either the
+/// repository root or, for repositories with several grammars, each subdirectory containing a
+/// `grammar.js`.
+async fn generate_grammar(
+    language: Language,
+    directory: String,
+    abi_version: Option<u32>,
+) -> Result<(), String> {
+    let root = PathBuf::from(&directory);
+    let grammar_directories: Vec<PathBuf> = if root.join("grammar.js").is_file() {
+        vec![root]
+    } else {
+        let mut subdirectories: Vec<PathBuf> = fs::read_dir(&root)
+            .map_err(|e| format!("Failed to read {}: {}", directory, e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.join("grammar.js").is_file())
+            .collect();
+        subdirectories.sort();
+        subdirectories
+    };
+
+    for grammar_directory in grammar_directories {
+        let unresolved = unresolved_requires(&grammar_directory);
+        if !unresolved.is_empty() {
+            return Err(format!(
+                "Cannot generate {}, grammar.js requires modules that are neither vendored nor a \
+                 dependency: {}",
+                grammar_directory.display(),
+                unresolved.join(", ")
+            ));
+        }
+
+        let mut command = tokio::process::Command::new("tree-sitter");
+        command.current_dir(grammar_directory).arg("generate");
+        if let Some(abi_version) = abi_version {
+            command.arg("--abi").arg(abi_version.to_string());
+        }
+        let status = command.status().await;
+        check_command_status(status, &language)?;
+    }
+    Ok(())
+}
+
+fn check_command_status(
+    status: std::io::Result<std::process::ExitStatus>,
+    language: &Language,
+) -> Result<(), String> {
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!(
+            "Command exited with status: {}, for language: {}",
+            status, language.name
+        )),
+        Err(e) => Err(format!(
+            "Failed to execute command: {}, for language: {}",
+            e, language.name
+        )),
     }
 }
 
-async fn clone_repository(language: Language, directory: String) {
+/// Reports the errors of finished tasks, returning whether all of them succeeded.
+fn report_task_results(results: Vec<Result<Result<(), String>, tokio::task::JoinError>>) -> bool {
+    let mut passed = true;
+    for result in results {
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+            Err(e) => {
+                passed = false;
+                eprintln!("Join error: {:?}", e);
+            }
+        }
+    }
+    passed
+}
+
+/// Outcome of fetching a single grammar.
+struct FetchedGrammar {
+    commit: String,
+    pruned: Option<PruneReport>,
+}
+
+impl FetchedGrammar {
+    fn locked(&self, language: &Language) -> LockedLanguage {
+        LockedLanguage {
+            name: language.name.clone(),
+            git: language.git.clone(),
+            commit: self.commit.clone(),
+        }
+    }
+}
+
+async fn clone_repository(
+    language: Language,
+    directory: String,
+    patches: Vec<PathBuf>,
+    prune_rules: (Vec<String>, Vec<String>),
+) -> Option<FetchedGrammar> {
     let progress = ProgressBar::new_spinner();
     progress.set_style(ProgressStyle::default_spinner().tick_strings(&["-", "\\", "|", "/"]));
     progress.set_message(format!("Updating {}", language.name));
@@ -168,22 +682,42 @@ async fn clone_repository(language: Language, directory: String) {
     if let Err(e) = fs::remove_dir_all(&directory) {
         if e.kind() != std::io::ErrorKind::NotFound {
             progress.finish_with_message(format!("Failed update {}: {:?}", language.name, e));
-            return;
+            return None;
         }
     }
     match Repository::clone(&language.git, Path::new(&directory)) {
         Ok(repo) => {
             if let Some(commit_hash) = language.hash {
-                let _ = repo.set_head_detached(
-                    Oid::from_str(&commit_hash)
-                        .ok()
-                        .expect("Failed to checkout the specific commit"),
-                );
+                checkout_pinned_commit(&repo, &commit_hash)
+                    .expect("Failed to checkout the specific commit");
             }
+            let commit = repo
+                .head()
+                .ok()
+                .and_then(|head| head.target())
+                .map(|oid| oid.to_string())
+                .unwrap_or_default();
+            let patched = apply_patches(&repo, &patches);
             let git_folder = format!("{}/{}", &directory, ".git");
 
             fs::remove_dir_all(&git_folder).expect("Could not remove .git folder");
+            // an unpatched grammar must not be locked, generated or built
+            if let Err(e) = patched {
+                progress.finish_with_message(format!("Failed to patch {}", language.name));
+                eprintln!("Error: {}", e);
+                return None;
+            }
             progress.finish_with_message(format!("Successfully updated {}", language.name));
+
+            let (include, exclude) = prune_rules;
+            let pruned = match prune_grammar(Path::new(&directory), &include, &exclude) {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    None
+                }
+            };
+            Some(FetchedGrammar { commit, pruned })
         }
         Err(e) => {
             progress.finish_with_message(format!(
@@ -191,6 +725,1299 @@ async fn clone_repository(language: Language, directory: String) {
                 language.name,
                 e.message()
             ));
+            None
+        }
+    }
+}
+
+/// Creates a patch of the local edits to the vendored grammar of a language beyond its patches,
+/// leaving out the files its include and exclude globs prune. The patch being rewritten, if it is
+/// one of them, is not applied.
+pub fn language_patch_diff(
+    name: &str,
+    file_path: &Path,
+    directory: &Path,
+    output: Option<&Path>,
+) -> Result<String, String> {
+    let LanguageGrammarsTOML {
+        prune, languages, ..
+    } = read_languages_toml(file_path).map_err(|e| e.to_string())?;
+    let language = languages
+        .get(name)
+        .ok_or_else(|| format!("Language not found: {}", name))?;
+    let lockfile = read_lockfile(file_path).map_err(|e| e.to_string())?;
+    let commit = grammar_commit(&lockfile, name, language);
+    let patches: Vec<PathBuf> = language_patches(name, language, file_path)
+        .into_iter()
+        .filter(|patch| !output.is_some_and(|output| same_file(patch, output)))
+        .collect();
+    patch_diff(
+        language,
+        commit.as_deref(),
+        &patches,
+        directory,
+        prune.rules_for(language),
+    )
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Applies the include and exclude globs to already fetched grammars, without fetching them.
+/// Returns whether every grammar could be pruned.
+pub fn prune_languages(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+) -> bool {
+    let Some(LanguageGrammarsTOML {
+        prune, languages, ..
+    }) = load_languages_toml(&file_path)
+    else {
+        return false;
+    };
+
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut passed = true;
+    let mut total_report = PruneReport::default();
+    for (_, language) in &selected {
+        let (include, exclude) = prune.rules_for(language);
+        match prune_grammar(&directory.join(&language.name), &include, &exclude) {
+            Ok(report) => {
+                println!("Removed {} from {}", report, language.name);
+                total_report += report;
+            }
+            Err(e) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+    if all {
+        println!("Removed {} in total", total_report);
+    }
+    passed
+}
+
+/// Audits the licenses of all vendored grammars, writing the combined notices and a JSON summary.
+/// Returns `false` when a license is missing, unknown or not allowed.
+pub fn report_licenses(
+    file_path: PathBuf,
+    directory: PathBuf,
+    notices_path: PathBuf,
+    summary_path: PathBuf,
+) -> bool {
+    let Some(LanguageGrammarsTOML {
+        licenses,
+        languages,
+        ..
+    }) = load_languages_toml(&file_path)
+    else {
+        return false;
+    };
+    let Some(lockfile) = load_lockfile(&file_path) else {
+        return false;
+    };
+    let audits = audit_licenses(&languages, &lockfile, &licenses, &directory);
+
+    let mut passed = true;
+    for audit in &audits {
+        let spdx = audit.spdx.as_deref().unwrap_or("-");
+        match audit.status {
+            LicenseStatus::Allowed => println!("{}: {}", audit.name, spdx),
+            LicenseStatus::NotAllowed => {
+                passed = false;
+                eprintln!("{}: license {} is not allowed", audit.name, spdx);
+            }
+            LicenseStatus::Unknown => {
+                passed = false;
+                eprintln!(
+                    "{}: unknown license in {}",
+                    audit.name,
+                    audit.license_files.join(", ")
+                );
+            }
+            LicenseStatus::Missing => {
+                passed = false;
+                match &audit.declared {
+                    Some(declared) => eprintln!(
+                        "{}: missing license file, package.json declares {}",
+                        audit.name, declared
+                    ),
+                    None => eprintln!("{}: missing license file", audit.name),
+                }
+            }
+        }
+    }
+
+    fs::write(&notices_path, third_party_notices(&audits, &directory))
+        .expect("Failed to write third party notices");
+    let summary = serde_json::to_string_pretty(&audits).expect("Failed to serialize to JSON");
+    fs::write(&summary_path, summary + "\n").expect("Failed to write license summary");
+
+    passed
+}
+
+/// Creates a CycloneDX document for all grammars and WebAssembly artifacts, without fetching.
+pub fn generate_sbom(
+    file_path: PathBuf,
+    directory: PathBuf,
+    wasm_directory: PathBuf,
+) -> Option<String> {
+    let languages = load_languages(&file_path)?;
+    let lockfile = load_lockfile(&file_path)?;
+    let sbom = cyclonedx_sbom(&languages, &lockfile, &directory, &wasm_directory);
+    Some(serde_json::to_string_pretty(&sbom).expect("Failed to serialize to JSON") + "\n")
+}
+
+/// Validates the WebAssembly artifacts built from the grammars in the languages file, returning
+/// whether all of them can be loaded by the runtime.
+pub fn validate_artifacts(file_path: PathBuf, directory: PathBuf, wasm_directory: PathBuf) -> bool {
+    let Some(LanguageGrammarsTOML { abi, languages, .. }) = load_languages_toml(&file_path) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (language_name, language) in &languages {
+        let units = grammar_units(language, &directory.join(&language.name));
+        for file_name in artifact_file_names(language_name, language) {
+            let artifact = wasm_directory.join(file_name);
+            if !artifact.is_file() {
+                continue;
+            }
+            match validate_wasm_grammars(&artifact, &units, &abi) {
+                Ok(abi_version) => {
+                    println!("{}: ABI version {}", artifact.display(), abi_version)
+                }
+                Err(e) => {
+                    passed = false;
+                    eprintln!("Error: {}", e);
+                }
+            }
+        }
+    }
+    passed
+}
+
+/// Writes a bundle of the WebAssembly artifacts, queries and metadata of all grammars, returning
+/// whether every artifact passed validation and the bundle was written.
+pub fn bundle_grammars(
+    file_path: PathBuf,
+    directory: PathBuf,
+    wasm_directory: PathBuf,
+    output: PathBuf,
+    version: Option<String>,
+    archive: bool,
+    query_flavor: Option<String>,
+) -> bool {
+    let Some(LanguageGrammarsTOML { abi, languages, .. }) = load_languages_toml(&file_path) else {
+        return false;
+    };
+    let Some(lockfile) = load_lockfile(&file_path) else {
+        return false;
+    };
+    let written = create_bundle(
+        &languages,
+        &lockfile,
+        &abi,
+        &directory,
+        &wasm_directory,
+        version,
+        query_flavor.as_deref(),
+    )
+    .and_then(|bundle| {
+        let path = write_bundle(&bundle, &output, archive)?;
+        Ok((path, bundle.index.languages.len()))
+    });
+    match written {
+        Ok((path, count)) => {
+            println!("Bundled {} languages into {}", count, path);
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            false
+        }
+    }
+}
+
+/// Compiles grammars into `libtree-sitter-grammars.a`, or one `lib<name>.a` per language, along
+/// with a header declaring their entry points, returning whether every grammar was built.
+pub async fn build_static_libraries(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+    per_language: bool,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    // every grammar of a repository with several gets its own object and entry point
+    let mut units = Vec::new();
+    for (language_name, language) in selected {
+        let grammar_directory = directory.join(&language.name);
+        let language_units: Vec<GrammarUnit> = grammar_units(&language, &grammar_directory)
+            .into_iter()
+            .filter(|unit| {
+                grammar_directory
+                    .join(unit.src())
+                    .join("parser.c")
+                    .is_file()
+            })
+            .collect();
+        if language_units.is_empty() {
+            println!("Skipping {}, it has no src/parser.c", language_name);
+            continue;
+        }
+        let several = language_units.len() > 1;
+        for unit in language_units {
+            let label = match several {
+                true => format!("{} ({})", language_name, unit.symbol),
+                false => language_name.clone(),
+            };
+            units.push((label, language.clone(), unit));
+        }
+    }
+
+    let toolchain = match detect_native_toolchain() {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+
+    // entry points are the only global symbols left, so they alone have to be unique
+    let mut entry_points: BTreeMap<&str, &str> = BTreeMap::new();
+    for (label, _, unit) in &units {
+        if let Some(other) = entry_points.insert(&unit.symbol, label) {
+            eprintln!(
+                "Error: languages {} and {} both define tree_sitter_{}",
+                other, label, unit.symbol
+            );
+            return false;
+        }
+    }
+
+    let objects_directory = output.join("objects");
+    fs::create_dir_all(&objects_directory).expect("Failed to create objects directory");
+    let builds: Vec<_> = units
+        .into_iter()
+        .map(|(label, language, unit)| {
+            let toolchain = toolchain.clone();
+            let grammar_directory = directory.join(&language.name);
+            let object = objects_directory.join(format!("{}.o", unit.symbol));
+            tokio::spawn(async move {
+                build_native_object(&toolchain, &language, &grammar_directory, &unit, &object)
+                    .await
+                    .map(|symbol| (label, language, object, symbol))
+            })
+        })
+        .collect();
+
+    let mut passed = true;
+    let mut symbols = BTreeMap::new();
+    let mut language_objects: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for result in join_all(builds).await {
+        match result {
+            Ok(Ok((label, language, object, symbol))) => {
+                language_objects
+                    .entry(language.name)
+                    .or_default()
+                    .push(object);
+                symbols.insert(label, symbol);
+            }
+            Ok(Err(e)) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+            Err(e) => {
+                passed = false;
+                eprintln!("Join error: {:?}", e);
+            }
+        }
+    }
+
+    let mut objects = Vec::new();
+    for (language_name, mut built) in language_objects {
+        if per_language {
+            let archive = output.join(format!("lib{}.a", language_name));
+            match create_archive(&toolchain, &archive, &built).await {
+                Ok(()) => println!("Built {}", archive.display()),
+                Err(e) => {
+                    passed = false;
+                    eprintln!("Error: {}", e);
+                }
+            }
+        } else {
+            objects.append(&mut built);
+        }
+    }
+
+    if !per_language && !objects.is_empty() {
+        let archive = output.join(STATIC_LIBRARY_NAME);
+        match create_archive(&toolchain, &archive, &objects).await {
+            Ok(()) => println!(
+                "Built {} with {} grammars",
+                archive.display(),
+                objects.len()
+            ),
+            Err(e) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+
+    fs::remove_dir_all(&objects_directory).expect("Failed to remove objects directory");
+    if !symbols.is_empty() {
+        fs::write(output.join(HEADER_NAME), grammars_header(&symbols))
+            .expect("Failed to write header");
+    }
+    passed
+}
+
+/// Generates an aggregate Rust crate exposing all grammars behind cargo features, returning
+/// whether it was written.
+pub fn write_rust_crate(
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+    crate_name: String,
+    version: TreeSitterVersion,
+    query_flavor: Option<String>,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let generated = generate_rust_crate(
+        &languages,
+        &directory,
+        &output,
+        &crate_name,
+        &version,
+        query_flavor.as_deref(),
+    );
+    match generated {
+        Ok(included) => {
+            println!(
+                "Generated {} with {} grammars for tree-sitter {} in {}",
+                crate_name,
+                included.len(),
+                version.requirement,
+                output.display()
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            false
+        }
+    }
+}
+
+/// Regenerates the Rust bindings of grammars for a single `tree-sitter` version, along with a
+/// Cargo workspace in the grammars directory, returning whether all of them were written.
+pub fn regenerate_bindings(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    version: TreeSitterVersion,
+    query_flavor: Option<String>,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (_, language) in &selected {
+        let written = write_grammar_bindings(
+            language,
+            &directory.join(&language.name),
+            &version,
+            query_flavor.as_deref(),
+        );
+        match written {
+            Ok(()) => println!(
+                "Generated bindings of {} for tree-sitter {}",
+                language.name, version.requirement
+            ),
+            Err(e) => {
+                passed = false;
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+
+    let (workspace, members) = grammars_workspace(&languages, &directory);
+    fs::write(directory.join("Cargo.toml"), workspace).expect("Failed to write workspace");
+    println!(
+        "Wrote workspace with {} grammars to {}",
+        members,
+        directory.join("Cargo.toml").display()
+    );
+    passed
+}
+
+/// Describes the ABI versions of a grammar's parsers, e.g. `14` or `tsx 14, typescript 14`.
+fn describe_abi_versions(versions: &[abi::ParserAbi]) -> String {
+    let describe = |version: Option<u32>| version.map_or("-".to_string(), |v| v.to_string());
+    match versions {
+        [] => "-".to_string(),
+        [parser] => describe(parser.version),
+        parsers => parsers
+            .iter()
+            .map(|parser| format!("{} {}", parser.path, describe(parser.version)))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Lists the languages with the commit and ABI version of their vendored grammars, marking
+/// grammars whose ABI version is outside the supported range.
+pub fn list_languages(file_path: PathBuf, directory: PathBuf) -> bool {
+    let Some(LanguageGrammarsTOML { abi, languages, .. }) = load_languages_toml(&file_path) else {
+        return false;
+    };
+    let Some(lockfile) = load_lockfile(&file_path) else {
+        return false;
+    };
+    let width = languages.keys().map(|key| key.len()).max().unwrap_or(0);
+
+    for (language_name, language) in &languages {
+        let commit = grammar_commit(&lockfile, language_name, language)
+            .map_or("unpinned".to_string(), |commit| {
+                commit[..12.min(commit.len())].to_string()
+            });
+        let versions = grammar_abi_versions(language, &directory.join(&language.name));
+        let incompatible = versions
+            .iter()
+            .any(|parser| parser.version.is_some_and(|version| !abi.contains(version)));
+        println!(
+            "{:width$}  {:12}  ABI {}{}",
+            language_name,
+            commit,
+            describe_abi_versions(&versions),
+            if incompatible {
+                format!("  (incompatible, supported: {})", abi)
+            } else {
+                String::new()
+            },
+        );
+    }
+    true
+}
+
+/// Checks that the generated parsers of all grammars have an ABI version in the supported range,
+/// optionally regenerating the ones outside it for the newest supported version with the
+/// `tree-sitter` CLI. Returns whether all parsers are supported afterwards.
+pub async fn verify_grammars(file_path: PathBuf, directory: PathBuf, regenerate: bool) -> bool {
+    let Some(LanguageGrammarsTOML { abi, languages, .. }) = load_languages_toml(&file_path) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (language_name, language) in &languages {
+        let grammar_directory = directory.join(&language.name);
+        let mut versions = grammar_abi_versions(language, &grammar_directory);
+        let out_of_range = |versions: &[abi::ParserAbi]| {
+            versions
+                .iter()
+                .any(|parser| parser.version.is_some_and(|version| !abi.contains(version)))
+        };
+
+        if regenerate && out_of_range(&versions) {
+            println!("Regenerating {} for ABI version {}", language.name, abi.max);
+            let provisioned = dependency_closure(language_name, &languages).and_then(|closure| {
+                closure.iter().try_for_each(|key| {
+                    provision_node_modules(&languages[key], &languages, &directory)
+                        .map_err(|e| e.to_string())
+                })
+            });
+            let destination_directory = format!("{}{}", directory.display(), language.name);
+            let generated = match provisioned {
+                Ok(()) => {
+                    generate_grammar(language.clone(), destination_directory, Some(abi.max)).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = generated {
+                eprintln!("Error: {}", e);
+            }
+            versions = grammar_abi_versions(language, &grammar_directory);
+        }
+
+        if out_of_range(&versions) {
+            passed = false;
+            eprintln!(
+                "{}: ABI {} is outside the supported range {}",
+                language_name,
+                describe_abi_versions(&versions),
+                abi
+            );
+        } else if versions.iter().all(|parser| parser.version.is_none()) {
+            println!("{}: no generated parser", language_name);
+        } else {
+            println!(
+                "{}: ABI {}",
+                language_name,
+                describe_abi_versions(&versions)
+            );
+        }
+    }
+    passed
+}
+
+/// Checks the queries of grammars against their generated node types and prints every problem
+/// found with its file, line and column. Returns whether no problems were found.
+pub fn lint_queries(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut problems = 0;
+    for (_, language) in &selected {
+        match lint_grammar_queries(language, &directory.join(&language.name)) {
+            Some(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    eprintln!("{}", diagnostic);
+                }
+                problems += diagnostics.len();
+            }
+            None => println!("Skipping {}, it has no src/node-types.json", language.name),
+        }
+    }
+    if problems > 0 {
+        eprintln!(
+            "Found {} problem{} in queries",
+            problems,
+            if problems == 1 { "" } else { "s" }
+        );
+    }
+    problems == 0
+}
+
+/// Writes the queries of grammars, composed from every file their metadata lists including the
+/// ones of the vendored grammars they extend, to `<output>/<language>/<kind>.scm`, in the flavor
+/// selected for each language. With `canonical_captures`, the captures of highlights are rewritten
+/// to the `[captures]` vocabulary and the ones outside it reported. Returns whether every listed
+/// file could be resolved.
+pub fn export_queries(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+    query_flavor: Option<String>,
+    canonical_captures: bool,
+) -> bool {
+    let Some(LanguageGrammarsTOML {
+        captures,
+        languages,
+        ..
+    }) = load_languages_toml(&file_path)
+    else {
+        return false;
+    };
+    if canonical_captures && captures.vocabulary.is_empty() {
+        eprintln!("Please list the canonical capture names in the [captures] vocabulary.");
+        return false;
+    }
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (language_name, language) in &selected {
+        let grammar_directory = directory.join(&language.name);
+        if !grammar_directory.is_dir() {
+            println!("Skipping {}, it has not been fetched", language_name);
+            continue;
+        }
+        let grammar = root_grammar_metadata(&grammar_directory).unwrap_or_default();
+        let flavor = select_query_flavor(language, &grammar_directory, query_flavor.as_deref());
+        let language_output = output.join(language_name);
+        if language_output.exists() {
+            fs::remove_dir_all(&language_output).expect("Failed to remove exported queries");
+        }
+
+        let mut exported = Vec::new();
+        for kind in FLAVORED_QUERIES {
+            for path in grammar.queries(kind) {
+                if resolve_query_path(&grammar_directory, path).is_none() {
+                    passed = false;
+                    eprintln!(
+                        "Error: {} lists {} in its {} queries, which does not resolve to a file",
+                        language.name, path, kind
+                    );
+                }
+            }
+            let Some(mut query) = read_query(&grammar_directory, &grammar, kind, flavor.as_deref())
+            else {
+                continue;
+            };
+            if canonical_captures && kind == "highlights" {
+                match rewrite_captures(&query, &captures) {
+                    Ok((rewritten, unmapped)) => {
+                        query = rewritten;
+                        if !unmapped.is_empty() {
+                            let unmapped: Vec<String> =
+                                unmapped.iter().map(|name| format!("@{}", name)).collect();
+                            eprintln!(
+                                "Warning: {} highlights use captures outside the vocabulary: {}",
+                                language_name,
+                                unmapped.join(", ")
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        passed = false;
+                        eprintln!(
+                            "Error: Failed to parse the highlights of {}: {}",
+                            language_name, e
+                        );
+                    }
+                }
+            }
+            fs::create_dir_all(&language_output).expect("Failed to create query directory");
+            fs::write(language_output.join(format!("{}.scm", kind)), query)
+                .expect("Failed to write query");
+            exported.push(kind);
+        }
+        if exported.is_empty() {
+            println!("Skipping {}, it has no queries", language_name);
+        } else {
+            println!(
+                "Exported {} queries of {} to {}",
+                exported.join(", "),
+                language_name,
+                language_output.display()
+            );
+        }
+    }
+    passed
+}
+
+/// Lists the capture names used in the query files of grammars, per file, leaving out the ones
+/// only predicates use. Returns whether every query file could be read and parsed.
+pub fn list_captures(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (language_name, language) in &selected {
+        let grammar_directory = directory.join(&language.name);
+        let files = grammar_query_files(language, &grammar_directory);
+        if files.is_empty() {
+            continue;
+        }
+        println!("{}", language_name);
+        for file in files {
+            let path = grammar_directory.join(&file);
+            let source = match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    passed = false;
+                    eprintln!("Error: Failed to read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            match query_captures(&source) {
+                Ok(captures) => {
+                    let captures: Vec<String> = captures
+                        .iter()
+                        .filter(|capture| !is_private_capture(capture))
+                        .map(|capture| format!("@{}", capture))
+                        .collect();
+                    println!("  {}: {}", file, captures.join(" "));
+                }
+                Err(e) => {
+                    passed = false;
+                    eprintln!("Error: Failed to parse {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+    passed
+}
+
+/// Prints which languages the vendored grammars inject, the injected languages that are not
+/// vendored, and the injected names of vendored languages no `injection-regex` matches. Returns
+/// whether all injection queries could be read.
+pub fn report_injections(file_path: PathBuf, directory: PathBuf) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let graph = injection_graph(&languages, &directory);
+    let join = |names: &BTreeSet<String>| names.iter().cloned().collect::<Vec<_>>().join(", ");
+
+    println!("Injections:");
+    for (language_name, injected) in &graph.edges {
+        let mut targets: Vec<String> = injected.iter().cloned().collect();
+        if graph.dynamic.contains(language_name) {
+            targets.push("(languages named in the document)".to_string());
+        }
+        println!("  {} -> {}", language_name, targets.join(", "));
+    }
+
+    if !graph.not_vendored.is_empty() {
+        println!("Injected but not vendored:");
+        for (name, injecting) in &graph.not_vendored {
+            println!("  {} (injected by {})", name, join(injecting));
+        }
+    }
+    if !graph.unmatched.is_empty() {
+        println!("Not matched by any injection-regex:");
+        for (name, (language_name, injecting)) in &graph.unmatched {
+            println!(
+                "  {} (vendored as {}, injected by {})",
+                name,
+                language_name,
+                join(injecting)
+            );
+        }
+    }
+
+    for error in &graph.errors {
+        eprintln!("Error: {}", error);
+    }
+    graph.errors.is_empty()
+}
+
+/// Merges the node types of all grammars, including every grammar of repositories with several,
+/// into an index written to `output` as JSON. With `node`, the grammars having that node type,
+/// or with `field` the ones whose node type has that field, are printed instead. Returns whether
+/// every `node-types.json` could be read.
+pub fn index_node_types(
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+    node: Option<String>,
+    field: Option<String>,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let index = node_types_index(&languages, &directory);
+    for error in &index.errors {
+        eprintln!("Error: {}", error);
+    }
+
+    match (&node, &field) {
+        (Some(node), field) => {
+            let grammars = match field {
+                Some(field) => index.grammars_with_field(node, field),
+                None => index.grammars_with_node_type(node),
+            };
+            for grammar in &grammars {
+                let indexed = &index.grammars[*grammar];
+                match indexed.path.as_str() {
+                    "" => println!("{} ({})", grammar, indexed.language),
+                    path => println!("{} ({}, {})", grammar, indexed.language, path),
+                }
+            }
+            if grammars.is_empty() {
+                println!("No grammars found");
+            }
+        }
+        (None, Some(_)) => {
+            eprintln!("Please provide the node type of the field with --node.");
+            return false;
+        }
+        (None, None) => {
+            let json = serde_json::to_string_pretty(&index).expect("Failed to serialize index");
+            fs::write(&output, json + "\n").expect("Failed to write node types index");
+            println!(
+                "Indexed {} node types of {} grammars to {}",
+                index.node_types.len(),
+                index.grammars.len(),
+                output.display()
+            );
+        }
+    }
+    index.errors.is_empty()
+}
+
+/// Runs the corpus, highlight and tag tests of grammars with the `tree-sitter` CLI and prints
+/// how many passed and failed for each grammar, and the output of the ones that failed. With
+/// `builtin`, or if the CLI is not installed, only corpus tests are run, with the natively built
+/// grammars loaded into our own runtime. Returns whether the tests of every grammar passed.
+pub async fn test_grammars(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    builtin: bool,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    // without the CLI, corpus tests are run with the grammars loaded into our own runtime
+    let toolchain = if builtin || build::find_executable("tree-sitter").is_none() {
+        if !builtin {
+            println!("No tree-sitter CLI found, running corpus tests with the built-in runner");
+        }
+        match detect_native_toolchain() {
+            Ok(toolchain) => Some(toolchain),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+        }
+    } else {
+        None
+    };
+    let parsers_directory = parsers_directory(&directory);
+
+    let mut runs = Vec::new();
+    for (language_name, language) in selected {
+        let grammar_directory = directory.join(&language.name);
+        let suites = grammar_test_suites(&language, &grammar_directory);
+        if suites.is_empty() {
+            println!("Skipping {}, it has no tests", language_name);
+            continue;
+        }
+        for (unit, suites) in suites {
+            let unit_directory = grammar_directory.join(&unit.path);
+            // repositories with several grammars are tested once for each
+            let label = match unit.path.as_str() {
+                "" => language_name.clone(),
+                _ => format!("{} ({})", language_name, unit.symbol),
+            };
+            if !unit_directory.join("src/parser.c").is_file() {
+                println!("Skipping {}, it has no src/parser.c", label);
+                continue;
+            }
+            let Some(toolchain) = toolchain.clone() else {
+                runs.push(tokio::spawn(async move {
+                    (label, run_tree_sitter_test(&unit_directory).await)
+                }));
+                continue;
+            };
+            let Some(corpus) = suites.corpus else {
+                println!("Skipping {}, it has no corpus tests", label);
+                continue;
+            };
+            let language = language.clone();
+            let parsers_directory = parsers_directory.clone();
+            runs.push(tokio::spawn(async move {
+                let report = load_grammar(
+                    &toolchain,
+                    &language,
+                    &unit_directory,
+                    &unit.symbol,
+                    &parsers_directory,
+                )
+                .await
+                .and_then(|grammar| {
+                    run_corpus(&grammar, &unit.symbol, &unit_directory.join(corpus))
+                });
+                (label, report)
+            }));
         }
     }
+
+    let mut tested = 0;
+    let mut failed = Vec::new();
+    for result in join_all(runs).await {
+        let (label, report) = match result {
+            Ok(run) => run,
+            Err(e) => {
+                eprintln!("Join error: {:?}", e);
+                return false;
+            }
+        };
+        tested += 1;
+        match report {
+            Ok(report) if report.success => {
+                println!(
+                    "{}: {} passed, {} skipped",
+                    label, report.passed, report.skipped
+                );
+            }
+            Ok(report) => {
+                eprint!("{}", report.output);
+                eprintln!(
+                    "{}: {} passed, {} failed, {} skipped",
+                    label, report.passed, report.failed, report.skipped
+                );
+                failed.push(label);
+            }
+            Err(e) => {
+                eprintln!("Error: {}: {}", label, e);
+                failed.push(label);
+            }
+        }
+    }
+
+    if tested == 0 {
+        println!("No grammars with tests to run");
+    } else if failed.is_empty() {
+        println!("Tests passed for {} grammars", tested);
+    } else {
+        eprintln!(
+            "Tests failed for {} of {} grammars: {}",
+            failed.len(),
+            tested,
+            failed.join(", ")
+        );
+    }
+    failed.is_empty()
+}
+
+/// Parses the sample files of languages with their current grammar, loaded into our own runtime,
+/// and compares each tree with the snapshot stored in `<output>/<language>/`. Files whose tree
+/// changed or gained `ERROR` or `MISSING` nodes are printed, with a diff of their trees if `diff`
+/// is set. Snapshots of new samples are stored, and with `update` the changed and removed ones
+/// are replaced too. Returns whether all trees are unchanged, or `update` was set.
+pub async fn snapshot_grammars(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+    update: bool,
+    diff: bool,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let named = name.is_some();
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+    let mut sampled = Vec::new();
+    for (language_name, language) in selected {
+        match language_samples(&language_name, &language, &file_path) {
+            Some(samples) => sampled.push((language_name, language, samples)),
+            None if named => {
+                eprintln!(
+                    "Language {} has no samples, set its `samples` or add samples/{}/",
+                    language_name, language_name
+                );
+                return false;
+            }
+            None => println!("Skipping {}, it has no samples", language_name),
+        }
+    }
+
+    let toolchain = match detect_native_toolchain() {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+    let parsers_directory = parsers_directory(&directory);
+
+    let mut runs = Vec::new();
+    for (language_name, language, samples) in sampled {
+        let grammar_directory = directory.join(&language.name);
+        let unit =
+            language_grammar_unit(&language_name, &language, &grammar_directory).filter(|unit| {
+                grammar_directory
+                    .join(unit.src())
+                    .join("parser.c")
+                    .is_file()
+            });
+        let Some(unit) = unit else {
+            println!("Skipping {}, it has no src/parser.c", language_name);
+            continue;
+        };
+        let toolchain = toolchain.clone();
+        let parsers_directory = parsers_directory.clone();
+        let snapshots = output.join(&language_name);
+        runs.push(tokio::spawn(async move {
+            let snapshots = load_grammar(
+                &toolchain,
+                &language,
+                &grammar_directory.join(&unit.path),
+                &unit.symbol,
+                &parsers_directory,
+            )
+            .await
+            .and_then(|grammar| snapshot_samples(&grammar, &samples, &snapshots, update));
+            (language_name, snapshots)
+        }));
+    }
+
+    let mut passed = true;
+    for result in join_all(runs).await {
+        let (language_name, snapshots) = match result {
+            Ok(run) => run,
+            Err(e) => {
+                eprintln!("Join error: {:?}", e);
+                passed = false;
+                continue;
+            }
+        };
+        let snapshots = match snapshots {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                eprintln!("Error: {}: {}", language_name, e);
+                passed = false;
+                continue;
+            }
+        };
+
+        let count = |matches: fn(&SnapshotStatus) -> bool| {
+            snapshots
+                .iter()
+                .filter(|snapshot| matches(&snapshot.status))
+                .count()
+        };
+        println!(
+            "{}: {} unchanged, {} changed, {} with new errors, {} new, {} removed",
+            language_name,
+            count(|status| *status == SnapshotStatus::Unchanged),
+            count(|status| *status == SnapshotStatus::Changed),
+            count(|status| matches!(status, SnapshotStatus::NewErrors { .. })),
+            count(|status| *status == SnapshotStatus::New),
+            count(|status| *status == SnapshotStatus::Removed),
+        );
+        for snapshot in &snapshots {
+            let description = match &snapshot.status {
+                SnapshotStatus::Unchanged => continue,
+                SnapshotStatus::New => "new".to_string(),
+                SnapshotStatus::Changed => "changed".to_string(),
+                SnapshotStatus::NewErrors { before, after } => {
+                    format!("new errors ({} before, {} now)", before, after)
+                }
+                SnapshotStatus::Removed => "removed".to_string(),
+            };
+            println!("  {}: {}", snapshot.path, description);
+            if let Some(tree_diff) = snapshot.diff.as_ref().filter(|_| diff) {
+                println!("{}", tree_diff);
+            }
+            if !update && snapshot.status != SnapshotStatus::New {
+                passed = false;
+            }
+        }
+    }
+    if !passed && !update {
+        eprintln!("Parse trees differ from their snapshots, rerun with --update to accept them");
+    }
+    passed
+}
+
+/// Parses the corpus examples of grammars, or the files in `input`, with the natively built
+/// grammars loaded into our own runtime, and prints the throughput, peak memory and error nodes
+/// of each. With `all`, the files of a language are taken from `<input>/<language>/`. The results
+/// are written to `output` as JSON, keyed by language. Returns whether every grammar could be
+/// benchmarked.
+pub async fn bench_grammars(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    input: Option<PathBuf>,
+    iterations: usize,
+    output: PathBuf,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(lockfile) = load_lockfile(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let toolchain = match detect_native_toolchain() {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+    let parsers_directory = parsers_directory(&directory);
+
+    let mut passed = true;
+    let mut loads = Vec::new();
+    for (language_name, language) in selected {
+        let grammar_directory = directory.join(&language.name);
+        let unit =
+            language_grammar_unit(&language_name, &language, &grammar_directory).filter(|unit| {
+                grammar_directory
+                    .join(unit.src())
+                    .join("parser.c")
+                    .is_file()
+            });
+        let Some(unit) = unit else {
+            println!("Skipping {}, it has no src/parser.c", language_name);
+            continue;
+        };
+        let unit_directory = grammar_directory.join(&unit.path);
+        let inputs = match &input {
+            Some(input) => {
+                let input = match all {
+                    true => input.join(&language_name),
+                    false => input.clone(),
+                };
+                if !input.is_dir() {
+                    println!("Skipping {}, it has no {}", language_name, input.display());
+                    continue;
+                }
+                directory_inputs(&input)
+            }
+            None => match test_suites(&unit_directory).corpus {
+                Some(corpus) => corpus_inputs(&unit_directory.join(corpus), &unit.symbol),
+                None => {
+                    println!("Skipping {}, it has no corpus tests", language_name);
+                    continue;
+                }
+            },
+        };
+        let inputs = match inputs {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                eprintln!("Error: {}: {}", language_name, e);
+                passed = false;
+                continue;
+            }
+        };
+
+        let toolchain = toolchain.clone();
+        let parsers_directory = parsers_directory.clone();
+        let commit = grammar_commit(&lockfile, &language_name, &language);
+        loads.push(tokio::spawn(async move {
+            let grammar = load_grammar(
+                &toolchain,
+                &language,
+                &unit_directory,
+                &unit.symbol,
+                &parsers_directory,
+            )
+            .await;
+            (language_name, commit, inputs, grammar)
+        }));
+    }
+
+    // the grammars are benchmarked one at a time, so they do not compete for the CPU
+    let mut results = BTreeMap::new();
+    for result in join_all(loads).await {
+        let (language_name, commit, inputs, grammar) = match result {
+            Ok(load) => load,
+            Err(e) => {
+                eprintln!("Join error: {:?}", e);
+                passed = false;
+                continue;
+            }
+        };
+        let result = grammar.and_then(|grammar| bench_grammar(&grammar, &inputs, iterations));
+        match result {
+            Ok(result) => {
+                println!(
+                    "{}: {} inputs of {} in {:.3} s, {}/s, peak memory {}, {} ERROR and {} MISSING nodes in {} inputs",
+                    language_name,
+                    result.inputs,
+                    format_size(result.bytes as f64),
+                    result.seconds,
+                    format_size(result.bytes_per_second),
+                    format_size(result.peak_memory as f64),
+                    result.errors,
+                    result.missing,
+                    result.inputs_with_errors
+                );
+                results.insert(language_name, BenchResult { commit, ..result });
+            }
+            Err(e) => {
+                eprintln!("Error: {}: {}", language_name, e);
+                passed = false;
+            }
+        }
+    }
+
+    if !results.is_empty() {
+        let json = serde_json::to_string_pretty(&results).expect("Failed to serialize results");
+        fs::write(&output, json + "\n").expect("Failed to write benchmark results");
+        println!("Wrote benchmark results to {}", output.display());
+    }
+    passed
+}
+
+/// Generates a typed Rust module from the `node-types.json` of grammars, with a wrapper over
+/// `tree_sitter::Node` for every named node type, and writes it to `<output>/<language>.rs`.
+/// For repositories with several grammars, the one parsing the language itself is used. Returns
+/// whether a module could be generated for every selected grammar.
+pub fn codegen_rust(
+    name: Option<String>,
+    all: bool,
+    file_path: PathBuf,
+    directory: PathBuf,
+    output: PathBuf,
+) -> bool {
+    let Some(languages) = load_languages(&file_path) else {
+        return false;
+    };
+    let Some(selected) = select_languages(&languages, name, all) else {
+        return false;
+    };
+
+    let mut passed = true;
+    for (language_name, language) in selected {
+        let grammar_directory = directory.join(&language.name);
+        let node_types_path = language_grammar_unit(&language_name, &language, &grammar_directory)
+            .map(|unit| grammar_directory.join(unit.src()).join("node-types.json"))
+            .filter(|path| path.is_file());
+        let Some(node_types_path) = node_types_path else {
+            println!("Skipping {}, it has no src/node-types.json", language_name);
+            continue;
+        };
+        let node_types = match read_node_types(&node_types_path) {
+            Ok(node_types) => node_types,
+            Err(e) => {
+                eprintln!("Error: {}: {}", language_name, e);
+                passed = false;
+                continue;
+            }
+        };
+
+        let module = generate_rust_ast(&language_name, &node_types);
+        fs::create_dir_all(&output).expect("Failed to create output directory");
+        let path = output.join(format!("{}.rs", language_name.replace('-', "_")));
+        fs::write(&path, module).expect("Failed to write generated module");
+        println!("Generated {}", path.display());
+    }
+    passed
 }
@@ -0,0 +1,305 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::lockfile::grammar_commit;
+use crate::lockfile::Lockfile;
+use crate::Language;
+
+/// Licenses grammars may use unless `allow` is set in the `[licenses]` table.
+pub const DEFAULT_ALLOWED_LICENSES: [&str; 9] = [
+    "MIT",
+    "Apache-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "ISC",
+    "0BSD",
+    "Zlib",
+    "Unlicense",
+    "CC0-1.0",
+];
+
+/// File names that hold a grammar's license, compared case-insensitively up to the first `.`,
+/// `-` or `_`, so `LICENSE-MIT` and `LICENSE_1_0.txt` count as well.
+const LICENSE_FILE_NAMES: [&str; 4] = ["license", "licence", "copying", "unlicense"];
+
+/// Phrases identifying a license text, checked in order, so more specific licenses come first.
+const LICENSE_PHRASES: [(&str, &[&str]); 14] = [
+    ("Apache-2.0", &["Apache License", "Version 2.0"]),
+    ("MPL-2.0", &["Mozilla Public License Version 2.0"]),
+    (
+        "LGPL-3.0",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 3"],
+    ),
+    (
+        "LGPL-2.1",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 2.1"],
+    ),
+    ("GPL-3.0", &["GNU GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("GPL-2.0", &["GNU GENERAL PUBLIC LICENSE", "Version 2"]),
+    ("Artistic-2.0", &["The Artistic License 2.0"]),
+    ("CC0-1.0", &["CC0 1.0 Universal"]),
+    (
+        "Unlicense",
+        &["This is free and unencumbered software released into the public domain"],
+    ),
+    ("MIT", &["Permission is hereby granted, free of charge"]),
+    (
+        "ISC",
+        &["Permission to use, copy, modify, and/or distribute this software for any purpose"],
+    ),
+    (
+        "BSD-3-Clause",
+        &[
+            "Redistribution and use in source and binary forms",
+            "Neither the name",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["Redistribution and use in source and binary forms"],
+    ),
+    (
+        "Zlib",
+        &["This software is provided 'as-is', without any express or implied"],
+    ),
+];
+
+/// The global `[licenses]` table of the languages file.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LicensePolicy {
+    /// SPDX identifiers grammars may use, replacing `DEFAULT_ALLOWED_LICENSES`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+}
+
+impl LicensePolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether a license may be used, which for a choice like `MIT OR Apache-2.0` means any of
+    /// its alternatives may.
+    pub fn allows(&self, spdx: &str) -> bool {
+        spdx.split(" OR ")
+            .map(|spdx| spdx.trim())
+            .any(|spdx| match &self.allow {
+                Some(allow) => allow.iter().any(|allowed| allowed == spdx),
+                None => DEFAULT_ALLOWED_LICENSES.contains(&spdx),
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseStatus {
+    Allowed,
+    NotAllowed,
+    Unknown,
+    Missing,
+}
+
+/// The license found for a single vendored grammar.
+#[derive(Debug, Serialize, Clone)]
+pub struct LicenseAudit {
+    pub language: String,
+    pub name: String,
+    pub git: String,
+    /// Commit the grammar was fetched at
+    pub commit: Option<String>,
+    /// License files, relative to the grammar directory
+    pub license_files: Vec<String>,
+    /// SPDX identifier detected from the license files, e.g. `MIT OR Apache-2.0` for a grammar
+    /// with both `LICENSE-MIT` and `LICENSE-APACHE`
+    pub spdx: Option<String>,
+    /// License declared in the grammar's `package.json`
+    pub declared: Option<String>,
+    pub status: LicenseStatus,
+}
+
+/// Detects the license of every vendored grammar and checks it against the policy.
+pub fn audit_licenses(
+    languages: &BTreeMap<String, Language>,
+    lockfile: &Lockfile,
+    policy: &LicensePolicy,
+    directory: &Path,
+) -> Vec<LicenseAudit> {
+    languages
+        .iter()
+        .map(|(language_name, language)| {
+            let grammar_directory = directory.join(&language.name);
+            let license_files = find_license_files(&grammar_directory);
+            let spdx = detect_license(&grammar_directory, &license_files);
+            let status = match &spdx {
+                _ if license_files.is_empty() => LicenseStatus::Missing,
+                None => LicenseStatus::Unknown,
+                Some(spdx) if policy.allows(spdx) => LicenseStatus::Allowed,
+                Some(_) => LicenseStatus::NotAllowed,
+            };
+
+            LicenseAudit {
+                language: language_name.clone(),
+                name: language.name.clone(),
+                git: language.git.clone(),
+                commit: grammar_commit(lockfile, language_name, language),
+                license_files,
+                spdx,
+                declared: declared_license(&grammar_directory),
+                status,
+            }
+        })
+        .collect()
+}
+
+/// Returns the license files at the root of a grammar directory in file name order, e.g.
+/// `LICENSE`, `COPYING.txt` or both `LICENSE-APACHE` and `LICENSE-MIT`.
+pub fn find_license_files(grammar_directory: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(grammar_directory) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|file_name| {
+            let stem = file_name.split(['.', '-', '_']).next().unwrap_or_default();
+            LICENSE_FILE_NAMES.contains(&stem.to_lowercase().as_str())
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Detects the license of a grammar from its license files, offering the choice between them
+/// when they hold different licenses, e.g. `Apache-2.0 OR MIT`.
+pub fn detect_license(grammar_directory: &Path, license_files: &[String]) -> Option<String> {
+    let mut licenses: Vec<String> = Vec::new();
+    for file in license_files {
+        let Some(spdx) = fs::read_to_string(grammar_directory.join(file))
+            .ok()
+            .and_then(|text| detect_spdx(&text))
+        else {
+            continue;
+        };
+        if !licenses.contains(&spdx) {
+            licenses.push(spdx);
+        }
+    }
+    (!licenses.is_empty()).then(|| licenses.join(" OR "))
+}
+
+/// Reads the license files of a grammar into a single text.
+pub fn read_license_texts(grammar_directory: &Path, license_files: &[String]) -> Option<String> {
+    let texts: Vec<String> = license_files
+        .iter()
+        .filter_map(|file| fs::read_to_string(grammar_directory.join(file)).ok())
+        .map(|text| text.trim_end().to_string())
+        .collect();
+    (!texts.is_empty()).then(|| texts.join("\n\n"))
+}
+
+/// Detects the SPDX identifier of a license text, preferring an explicit
+/// `SPDX-License-Identifier` line over matching well-known phrases.
+pub fn detect_spdx(text: &str) -> Option<String> {
+    if let Some(line) = text
+        .lines()
+        .find_map(|line| line.split_once("SPDX-License-Identifier:"))
+    {
+        return Some(line.1.trim().to_string());
+    }
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    LICENSE_PHRASES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| text.contains(phrase)))
+        .map(|(spdx, _)| spdx.to_string())
+}
+
+fn declared_license(grammar_directory: &Path) -> Option<String> {
+    let package_json = fs::read_to_string(grammar_directory.join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&package_json).ok()?;
+    package
+        .get("license")
+        .and_then(|license| license.as_str())
+        .map(|license| license.to_string())
+}
+
+/// Combines the license texts of all vendored grammars into a single notices file.
+pub fn third_party_notices(audits: &[LicenseAudit], directory: &Path) -> String {
+    let mut notices = String::from(
+        "THIRD-PARTY SOFTWARE NOTICES\n\n\
+         This repository vendors the following tree-sitter grammars, each available under its own license.\n",
+    );
+
+    for audit in audits {
+        let separator = "=".repeat(80);
+        let _ = writeln!(notices, "\n{}\n{} ({})", separator, audit.name, audit.git);
+        if let Some(commit) = &audit.commit {
+            let _ = writeln!(notices, "Commit: {}", commit);
+        }
+        let license = audit
+            .spdx
+            .as_deref()
+            .or(audit.declared.as_deref())
+            .unwrap_or("unknown");
+        let _ = writeln!(notices, "License: {}\n{}\n", license, separator);
+
+        let grammar_directory = directory.join(&audit.name);
+        match read_license_texts(&grammar_directory, &audit.license_files) {
+            Some(text) => notices.push_str(&text),
+            None => notices.push_str("No license file is included with this grammar."),
+        }
+        notices.push('\n');
+    }
+    notices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_the_licenses_of_every_license_file() {
+        let root = std::env::temp_dir().join(format!("licenses-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let files = [
+            (
+                "LICENSE-APACHE",
+                "Apache License\nVersion 2.0, January 2004",
+            ),
+            (
+                "LICENSE-MIT",
+                "Permission is hereby granted, free of charge, to any person",
+            ),
+            ("README.md", "# Test"),
+        ];
+        for (file, contents) in files {
+            fs::write(root.join(file), contents).unwrap();
+        }
+
+        let license_files = find_license_files(&root);
+        let spdx = detect_license(&root, &license_files);
+        let text = read_license_texts(&root, &license_files).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(license_files, ["LICENSE-APACHE", "LICENSE-MIT"]);
+        assert_eq!(spdx.as_deref(), Some("Apache-2.0 OR MIT"));
+        assert!(text.starts_with("Apache License") && text.ends_with("to any person"));
+    }
+
+    #[test]
+    fn allows_a_choice_with_any_allowed_license() {
+        let policy = LicensePolicy {
+            allow: Some(vec!["MIT".to_string()]),
+        };
+        assert!(policy.allows("Apache-2.0 OR MIT"));
+        assert!(!policy.allows("Apache-2.0"));
+        assert!(LicensePolicy::default().allows("MIT"));
+        assert!(!LicensePolicy::default().allows("GPL-3.0"));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::check::line_and_column;
+use crate::metadata::grammar_units;
+use crate::metadata::query_files;
+use crate::metadata::read_grammar_metadata;
+use crate::metadata::METADATA_QUERIES;
+use crate::node_types::read_extra_node_types;
+use crate::node_types::read_node_types;
+use crate::node_types::NodeType;
+use crate::node_types::NodeTypeNames;
+use crate::query::parse_query;
+use crate::query::Name;
+use crate::query::Pattern;
+use crate::query::PatternKind;
+use crate::query::Predicate;
+use crate::query::PredicateArgument;
+use crate::Language;
+
+/// Node types every grammar has, without being listed in its `node-types.json`.
+const BUILTIN_NODE_TYPES: [&str; 3] = ["_", "ERROR", "MISSING"];
+
+/// A problem in a query file, located by line and column (both 1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryDiagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// Checks every `.scm` file of a grammar repository against the `node-types.json` of the grammars
+/// using it: files listed in a grammar's metadata and files in its `queries/` directory. Files in
+/// the `queries/` directory of a repository with several grammars that none of them lists are
+/// checked against the node types of all of them. Returns `None` if no grammar of the repository
+/// has a generated `node-types.json`.
+pub fn lint_grammar_queries(
+    language: &Language,
+    grammar_directory: &Path,
+) -> Option<Vec<QueryDiagnostic>> {
+    let metadata = read_grammar_metadata(grammar_directory);
+    let mut all_names = NodeTypeNames::default();
+    let mut files: BTreeMap<String, NodeTypeNames> = BTreeMap::new();
+    let mut found_node_types = false;
+
+    for unit in grammar_units(language, grammar_directory) {
+        let node_types_path = grammar_directory.join(unit.src()).join("node-types.json");
+        if !node_types_path.is_file() {
+            continue;
+        }
+        found_node_types = true;
+        let mut node_types = match read_node_types(&node_types_path) {
+            Ok(node_types) => node_types,
+            Err(e) => {
+                return Some(vec![QueryDiagnostic {
+                    path: node_types_path,
+                    line: 1,
+                    column: 1,
+                    message: e,
+                }])
+            }
+        };
+        let extras =
+            read_extra_node_types(&grammar_directory.join(unit.src()).join("grammar.json"));
+        node_types.extend(extras.into_iter().map(|kind| NodeType {
+            kind,
+            named: true,
+            fields: BTreeMap::new(),
+            children: None,
+            subtypes: Vec::new(),
+        }));
+        all_names.add(&node_types);
+
+        let mut unit_files = query_files(grammar_directory, &unit.path);
+        let listed = metadata.iter().filter(|grammar| match unit.path.as_str() {
+            "" => grammar.is_root(),
+            path => grammar.path.as_deref() == Some(path),
+        });
+        for grammar in listed {
+            for kind in METADATA_QUERIES {
+                unit_files.extend(grammar.queries(kind).iter().cloned());
+            }
+        }
+        for file in unit_files {
+            files.entry(file).or_default().add(&node_types);
+        }
+    }
+    if !found_node_types {
+        return None;
+    }
+
+    for file in query_files(grammar_directory, "") {
+        files.entry(file).or_insert_with(|| all_names.clone());
+    }
+
+    let mut diagnostics = Vec::new();
+    for (file, names) in &files {
+        let path = grammar_directory.join(file);
+        // metadata may list queries of other grammars, e.g. in `node_modules/`
+        let Ok(source) = fs::read_to_string(&path) else {
+            continue;
+        };
+        for (offset, message) in lint_query(&source, names) {
+            let (line, column) = line_and_column(&source, offset);
+            diagnostics.push(QueryDiagnostic {
+                path: path.clone(),
+                line,
+                column,
+                message,
+            });
+        }
+    }
+    Some(diagnostics)
+}
+
+/// Checks a query against the names of a grammar's node types, returning the byte offset and
+/// message of each problem. A syntax error stops the check, like it stops the runtime.
+pub fn lint_query(source: &str, names: &NodeTypeNames) -> Vec<(usize, String)> {
+    let patterns = match parse_query(source) {
+        Ok(patterns) => patterns,
+        Err(e) => return vec![(e.offset, e.message)],
+    };
+
+    let mut problems = Vec::new();
+    for pattern in &patterns {
+        let mut captures = BTreeSet::new();
+        pattern.walk(&mut |pattern| {
+            captures.extend(pattern.captures.iter().map(|capture| capture.text.as_str()));
+        });
+        lint_pattern(pattern, None, names, &captures, &mut problems);
+    }
+    problems
+}
+
+fn lint_pattern(
+    pattern: &Pattern,
+    parent: Option<&str>,
+    names: &NodeTypeNames,
+    captures: &BTreeSet<&str>,
+    problems: &mut Vec<(usize, String)>,
+) {
+    if let Some(field) = &pattern.field {
+        lint_field(field, parent, names, problems);
+    }
+
+    match &pattern.kind {
+        PatternKind::Node {
+            name,
+            supertype,
+            children,
+            negated_fields,
+        } => {
+            if let Some(supertype) = supertype {
+                if !names.supertypes.contains(&supertype.text) {
+                    problems.push((
+                        supertype.span.start,
+                        format!("invalid supertype '{}'", supertype.text),
+                    ));
+                }
+            }
+            let builtin = BUILTIN_NODE_TYPES.contains(&name.text.as_str());
+            // subtypes may be anonymous, e.g. `(expression/"this")`
+            let subtype_is_anonymous = supertype.is_some() && names.anonymous.contains(&name.text);
+            if !builtin && !names.named.contains(&name.text) && !subtype_is_anonymous {
+                let message = if names.anonymous.contains(&name.text) {
+                    format!(
+                        "'{}' is an anonymous node type, match it with \"{}\"",
+                        name.text, name.text
+                    )
+                } else {
+                    format!("invalid node type '{}'", name.text)
+                };
+                problems.push((name.span.start, message));
+            }
+
+            let parent = (!builtin).then_some(name.text.as_str());
+            for field in negated_fields {
+                lint_field(field, parent, names, problems);
+            }
+            for child in children {
+                lint_pattern(child, parent, names, captures, problems);
+            }
+        }
+        PatternKind::Anonymous(name) => {
+            if !names.anonymous.contains(&name.text) {
+                problems.push((
+                    name.span.start,
+                    format!(
+                        "invalid anonymous node type \"{}\"",
+                        name.text.escape_default()
+                    ),
+                ));
+            }
+        }
+        PatternKind::Alternation(children) | PatternKind::Group(children) => {
+            for child in children {
+                lint_pattern(child, parent, names, captures, problems);
+            }
+        }
+        PatternKind::Predicate(predicate) => lint_predicate(predicate, captures, problems),
+        PatternKind::Wildcard | PatternKind::Anchor => {}
+    }
+}
+
+/// Checks that a field exists, and that its parent node type has it.
+fn lint_field(
+    field: &Name,
+    parent: Option<&str>,
+    names: &NodeTypeNames,
+    problems: &mut Vec<(usize, String)>,
+) {
+    if !names.fields.contains(&field.text) {
+        problems.push((field.span.start, format!("invalid field '{}'", field.text)));
+        return;
+    }
+    let Some(parent) = parent else {
+        return;
+    };
+    // supertypes stand for nodes with different fields
+    if !names.named.contains(parent) || names.supertypes.contains(parent) {
+        return;
+    }
+    let has_field = names
+        .node_fields
+        .get(parent)
+        .is_some_and(|fields| fields.contains(&field.text));
+    if !has_field {
+        problems.push((
+            field.span.start,
+            format!("node type '{}' has no field '{}'", parent, field.text),
+        ));
+    }
+}
+
+/// Checks the arguments of the predicates the runtime and editors agree on, and that every
+/// capture a predicate refers to is defined by its pattern.
+fn lint_predicate(
+    predicate: &Predicate,
+    captures: &BTreeSet<&str>,
+    problems: &mut Vec<(usize, String)>,
+) {
+    use PredicateArgument::*;
+
+    let arguments = &predicate.arguments;
+    let capture_first = matches!(arguments.first(), Some(Capture(_)));
+    let rest = arguments.get(1..).unwrap_or_default();
+    let expected = match predicate.name.text.as_str() {
+        "eq?" | "not-eq?" | "any-eq?" | "any-not-eq?" => {
+            (!(arguments.len() == 2 && capture_first && !matches!(arguments[1], Identifier(_))))
+                .then_some("a capture and a capture or string")
+        }
+        "match?" | "not-match?" | "any-match?" | "any-not-match?" | "lua-match?" => {
+            (!(arguments.len() == 2 && capture_first && matches!(arguments[1], String(_))))
+                .then_some("a capture and a regular expression string")
+        }
+        "any-of?" | "not-any-of?" => (!(capture_first
+            && !rest.is_empty()
+            && rest.iter().all(|argument| matches!(argument, String(_)))))
+        .then_some("a capture and one or more strings"),
+        "set!" => (!(1..=3).contains(&arguments.len())).then_some("one to three arguments"),
+        "is?" | "is-not?" => {
+            (!(1..=2).contains(&arguments.len())).then_some("one or two arguments")
+        }
+        _ => None,
+    };
+    if let Some(expected) = expected {
+        problems.push((
+            predicate.name.span.start,
+            format!("#{} expects {}", predicate.name.text, expected),
+        ));
+    }
+
+    for argument in arguments {
+        if let Capture(capture) = argument {
+            if !captures.contains(capture.text.as_str()) {
+                problems.push((
+                    capture.span.start,
+                    format!("capture '@{}' is not defined in this pattern", capture.text),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::check::parse_languages_file;
+use crate::check::LoadError;
+use crate::Language;
+
+/// The commit a language's grammar was fetched at.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LockedLanguage {
+    pub name: String,
+    pub git: String,
+    pub commit: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub languages: BTreeMap<String, LockedLanguage>,
+}
+
+/// Returns the path of the lockfile belonging to a languages file, e.g. `languages.lock`.
+pub fn lockfile_path(file_path: &Path) -> PathBuf {
+    file_path.with_extension("lock")
+}
+
+/// Reads the lockfile belonging to a languages file, which is empty before the first fetch.
+pub fn read_lockfile(file_path: &Path) -> Result<Lockfile, LoadError> {
+    let path = lockfile_path(file_path);
+    match fs::read_to_string(&path) {
+        Ok(lock_contents) => parse_languages_file(&path, &lock_contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Lockfile::default()),
+        Err(e) => Err(LoadError::Read {
+            path,
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Records the commits languages were just fetched at, keeping the entries of other languages.
+pub fn update_lockfile(
+    file_path: &Path,
+    fetched: Vec<(String, LockedLanguage)>,
+) -> Result<(), LoadError> {
+    if fetched.is_empty() {
+        return Ok(());
+    }
+
+    let mut lockfile = read_lockfile(file_path)?;
+    lockfile.languages.extend(fetched);
+
+    let comment =
+        "# Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars fetch`.\n\n";
+
+    let updated_lock = format!(
+        "{}{}",
+        comment,
+        toml::to_string_pretty(&lockfile).expect("Failed to serialize to TOML")
+    );
+    fs::write(lockfile_path(file_path), updated_lock).expect("Failed to write updated lockfile");
+    Ok(())
+}
+
+/// Returns the commit a grammar was fetched at according to the lockfile, or else the commit it
+/// is pinned to in the languages file.
+pub fn grammar_commit(
+    lockfile: &Lockfile,
+    language_name: &str,
+    language: &Language,
+) -> Option<String> {
+    lockfile
+        .languages
+        .get(language_name)
+        .filter(|locked| locked.git == language.git)
+        .map(|locked| locked.commit.clone())
+        .or_else(|| language.hash.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(name: &str, commit: &str) -> LockedLanguage {
+        LockedLanguage {
+            name: name.to_string(),
+            git: format!("https://github.com/tree-sitter/{}", name),
+            commit: commit.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_fetched_commits() {
+        let root = std::env::temp_dir().join(format!("lockfile-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let file_path = root.join("languages.toml");
+
+        let empty = read_lockfile(&file_path).unwrap();
+        update_lockfile(
+            &file_path,
+            vec![
+                ("c".to_string(), locked("tree-sitter-c", "aaa")),
+                ("go".to_string(), locked("tree-sitter-go", "bbb")),
+            ],
+        )
+        .unwrap();
+        update_lockfile(
+            &file_path,
+            vec![("c".to_string(), locked("tree-sitter-c", "ccc"))],
+        )
+        .unwrap();
+        let lockfile = read_lockfile(&file_path).unwrap();
+        let contents = fs::read_to_string(root.join("languages.lock")).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(empty.languages.is_empty());
+        assert!(contents.starts_with("# Automatically generated"));
+        assert_eq!(lockfile.languages.len(), 2);
+        assert_eq!(lockfile.languages["c"].commit, "ccc");
+        assert_eq!(lockfile.languages["go"].commit, "bbb");
+    }
+
+    #[test]
+    fn locates_parse_errors() {
+        let root = std::env::temp_dir().join(format!("lockfile-error-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let file_path = root.join("languages.toml");
+        fs::write(
+            root.join("languages.lock"),
+            "[languages.c]\nname = \"tree-sitter-c\"\ncommit = 1\n",
+        )
+        .unwrap();
+
+        let error = read_lockfile(&file_path).unwrap_err();
+        let _ = fs::remove_dir_all(&root);
+
+        let LoadError::Parse { path, diagnostic } = error else {
+            panic!("expected a parse error, got {:?}", error);
+        };
+        assert_eq!(path, root.join("languages.lock"));
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 10));
+    }
+
+    #[test]
+    fn prefers_the_locked_commit_of_the_same_repository() {
+        let mut lockfile = Lockfile::default();
+        lockfile
+            .languages
+            .insert("c".to_string(), locked("tree-sitter-c", "locked"));
+        let language = Language::new(
+            "tree-sitter-c".to_string(),
+            "https://github.com/tree-sitter/tree-sitter-c".to_string(),
+            Some("pinned".to_string()),
+        );
+        let moved = Language::new(
+            "tree-sitter-c".to_string(),
+            "https://github.com/someone/tree-sitter-c".to_string(),
+            Some("pinned".to_string()),
+        );
+
+        assert_eq!(
+            grammar_commit(&lockfile, "c", &language).as_deref(),
+            Some("locked")
+        );
+        assert_eq!(
+            grammar_commit(&lockfile, "c", &moved).as_deref(),
+            Some("pinned")
+        );
+        assert_eq!(
+            grammar_commit(&lockfile, "go", &moved).as_deref(),
+            Some("pinned")
+        );
+    }
+}
@@ -1,9 +1,35 @@
+use std::fs;
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 
 use tree_sitter_grammars::add_language_grammar_to_toml;
+use tree_sitter_grammars::bench_grammars;
+use tree_sitter_grammars::build::WasmToolchain;
+use tree_sitter_grammars::build_static_libraries;
+use tree_sitter_grammars::bundle_grammars;
+use tree_sitter_grammars::check::check_languages_toml;
+use tree_sitter_grammars::codegen_rust;
+use tree_sitter_grammars::export_queries;
+use tree_sitter_grammars::generate_sbom;
+use tree_sitter_grammars::index_node_types;
+use tree_sitter_grammars::language_patch_diff;
+use tree_sitter_grammars::lint_queries;
+use tree_sitter_grammars::list_captures;
+use tree_sitter_grammars::list_languages;
+use tree_sitter_grammars::prune_languages;
+use tree_sitter_grammars::regenerate_bindings;
+use tree_sitter_grammars::report_injections;
+use tree_sitter_grammars::report_licenses;
+use tree_sitter_grammars::rust_crate::TreeSitterVersion;
+use tree_sitter_grammars::rust_crate::DEFAULT_TREE_SITTER_VERSION;
+use tree_sitter_grammars::snapshot_grammars;
+use tree_sitter_grammars::test_grammars;
 use tree_sitter_grammars::update_language;
+use tree_sitter_grammars::validate_artifacts;
+use tree_sitter_grammars::verify_grammars;
+use tree_sitter_grammars::write_rust_crate;
+use tree_sitter_grammars::FetchOptions;
 use tree_sitter_grammars::Language;
 
 #[derive(Parser)]
@@ -19,6 +45,26 @@ struct Cli {
     /// Path to directory containing grammar repositories
     #[arg(short, long, default_value = "./grammars/", global = true)]
     directory: PathBuf,
+
+    /// Variant of the queries to ship, e.g. 'helix' for `helix-highlights.scm`, overriding the
+    /// `query_flavor` of each language
+    #[arg(long, global = true)]
+    query_flavor: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum CodegenTarget {
+    /// Generate a Rust module with a type over `tree_sitter::Node` for every named node type
+    Rust {
+        /// Name of the language to generate the module for, e.g. 'rust'
+        name: Option<String>,
+        /// Use this flag to generate modules for all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Directory to write the modules to, as `<language>.rs`
+        #[arg(short, long, default_value = "./ast/")]
+        output: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
@@ -34,9 +80,24 @@ enum Commands {
         /// Optional git hash to checkout from the grammar repository
         #[arg(long)]
         hash: Option<String>,
+        /// Language whose grammar is required by this grammar, e.g. 'c' for 'cpp'
+        #[arg(long = "dependency")]
+        dependencies: Vec<String>,
+        /// Other name the language is known by, e.g. 'sh' for 'bash'
+        #[arg(long = "alias")]
+        aliases: Vec<String>,
         /// Whether we want to compile the grammar to WebAssembly
         #[arg(short, long)]
         wasm: bool,
+        /// Whether we want to regenerate the parser from `grammar.js`
+        #[arg(long)]
+        generate: bool,
+        /// Whether we want to rebuild the grammar even if its sources are unchanged
+        #[arg(long)]
+        force: bool,
+        /// Toolchain to compile to WebAssembly with: emcc, docker, podman or wasi-sdk [default: detected]
+        #[arg(long)]
+        wasm_toolchain: Option<WasmToolchain>,
     },
     /// Fetch the tree-sitter grammar(s)
     Fetch {
@@ -49,6 +110,220 @@ enum Commands {
         /// Whether we want to compile the grammar to WebAssembly
         #[arg(short, long)]
         wasm: bool,
+        /// Whether we want to regenerate the parser(s) from `grammar.js`
+        #[arg(long)]
+        generate: bool,
+        /// Whether we want to rebuild grammars even if their sources are unchanged
+        #[arg(long)]
+        force: bool,
+        /// Toolchain to compile to WebAssembly with: emcc, docker, podman or wasi-sdk [default: detected]
+        #[arg(long)]
+        wasm_toolchain: Option<WasmToolchain>,
+    },
+    /// Remove files excluded by the include/exclude globs from fetched grammar(s)
+    Prune {
+        /// Name of the language grammar to prune, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to prune all grammars for all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Check the `languages.toml` file for problems
+    Check,
+    /// Audit the licenses of the fetched grammars and write their combined notices
+    Licenses {
+        /// Path to write the combined license texts to
+        #[arg(long, default_value = "./THIRD_PARTY_NOTICES")]
+        notices: PathBuf,
+        /// Path to write the JSON summary of the detected licenses to
+        #[arg(long, default_value = "./licenses.json")]
+        summary: PathBuf,
+    },
+    /// Export a CycloneDX SBOM of the grammars and their WebAssembly artifacts
+    Sbom {
+        /// Path to directory containing compiled WebAssembly grammars
+        #[arg(long, default_value = "./wasm/")]
+        wasm_directory: PathBuf,
+        /// Path to write the SBOM to, e.g. 'sbom.cdx.json' [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compile grammars into a static library and a header declaring their entry points
+    StaticLib {
+        /// Name of the language grammar to compile, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to compile all grammars with a generated parser
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Directory to write the libraries and header to
+        #[arg(short, long, default_value = "./native/")]
+        output: PathBuf,
+        /// Whether we want one archive per language instead of a single one
+        #[arg(long)]
+        per_language: bool,
+    },
+    /// Regenerate the Rust bindings of grammar(s) for a single tree-sitter version
+    Bindings {
+        /// Name of the language grammar to regenerate the bindings of, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to regenerate the bindings of all grammars
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Version of the `tree-sitter` crate the bindings are written against
+        #[arg(long, default_value = DEFAULT_TREE_SITTER_VERSION)]
+        tree_sitter_version: TreeSitterVersion,
+    },
+    /// Generate a Rust crate exposing all grammars behind one cargo feature per grammar
+    RustCrate {
+        /// Directory to write the crate to
+        #[arg(short, long, default_value = "./rust/")]
+        output: PathBuf,
+        /// Name of the generated crate
+        #[arg(long, default_value = "tree-sitter-languages")]
+        crate_name: String,
+        /// Version of the `tree-sitter` crate all grammars are built against
+        #[arg(long, default_value = DEFAULT_TREE_SITTER_VERSION)]
+        tree_sitter_version: TreeSitterVersion,
+    },
+    /// Bundle the WebAssembly grammars with their queries and metadata for web consumers
+    Bundle {
+        /// Path to directory containing compiled WebAssembly grammars
+        #[arg(long, default_value = "./wasm/")]
+        wasm_directory: PathBuf,
+        /// Directory to write the bundle to
+        #[arg(short, long, default_value = "./dist/")]
+        output: PathBuf,
+        /// Version of the bundle, e.g. '2024.1' [default: digest of its contents]
+        #[arg(long = "bundle-version")]
+        version: Option<String>,
+        /// Whether we want to write a `.tar.zst` archive instead of a directory
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Check that compiled WebAssembly grammars can be loaded by the runtime
+    Validate {
+        /// Path to directory containing compiled WebAssembly grammars
+        #[arg(long, default_value = "./wasm/")]
+        wasm_directory: PathBuf,
+    },
+    /// Check the queries of grammar(s) against their node types
+    LintQueries {
+        /// Name of the language whose queries to check, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to check the queries of all grammars for all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Export the queries of grammar(s) composed with the queries of the grammars they extend
+    Queries {
+        /// Name of the language whose queries to export, e.g. 'typescript'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to export the queries of all grammars for all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Directory to write a folder of queries per language to
+        #[arg(short, long, default_value = "./queries/")]
+        output: PathBuf,
+        /// Rewrite the captures of highlights to the `[captures]` vocabulary
+        #[arg(long)]
+        canonical_captures: bool,
+    },
+    /// List the capture names used in the queries of grammar(s)
+    Captures {
+        /// Name of the language whose captures to list, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to list the captures of all grammars for all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Report which languages the grammars inject and which injected languages are missing
+    Injections,
+    /// Merge the node types, fields and supertypes of all grammars into one index
+    NodeIndex {
+        /// File to write the index to as JSON
+        #[arg(short, long, default_value = "./node-index.json")]
+        output: PathBuf,
+        /// Print the grammars with this node type instead, e.g. 'function_definition'
+        #[arg(long)]
+        node: Option<String>,
+        /// Only print the grammars whose node type has this field, e.g. 'name'
+        #[arg(long)]
+        field: Option<String>,
+    },
+    /// Run the corpus, highlight and tag tests of grammar(s) with `tree-sitter test`
+    Test {
+        /// Name of the language whose grammar to test, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to test the grammars of all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Run only the corpus tests, with the built-in runner instead of the `tree-sitter` CLI
+        #[arg(long)]
+        builtin: bool,
+    },
+    /// Compare the parse trees of sample files with their stored snapshots
+    Snapshot {
+        /// Name of the language whose samples to parse, e.g. 'rust'
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Use this flag to parse the samples of all languages that have any
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Directory to store a folder of snapshots per language in
+        #[arg(short, long, default_value = "./snapshots/")]
+        output: PathBuf,
+        /// Replace the snapshots of changed and removed samples
+        #[arg(long)]
+        update: bool,
+        /// Print the diff of every changed tree
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Measure the parsing throughput, peak memory and error nodes of grammar(s)
+    Bench {
+        /// Name of the language whose grammar to benchmark, e.g. 'rust'
+        name: Option<String>,
+        /// Use this flag to benchmark the grammars of all languages listed
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Directory of files to parse instead of the corpus examples, with a folder per language
+        /// when used with --all
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        /// How often to parse every file
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+        /// File to write the results to as JSON
+        #[arg(short, long, default_value = "./bench.json")]
+        output: PathBuf,
+    },
+    /// Generate typed bindings to the syntax trees of grammars from their node types
+    Codegen {
+        #[command(subcommand)]
+        target: CodegenTarget,
+    },
+    /// List the languages with the commit and ABI version of their grammars
+    List,
+    /// Check that the generated parsers of all grammars have a supported ABI version
+    Verify {
+        /// Whether we want to regenerate parsers with an unsupported ABI version using `tree-sitter`
+        #[arg(long)]
+        regenerate: bool,
+    },
+    /// Create a patch from local edits to a grammar beyond its pinned commit and patches
+    PatchDiff {
+        /// Name of the language grammar to diff, e.g. 'rust'
+        name: String,
+        /// Path to write the patch to, e.g. 'patches/rust/0001-fix.patch' [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 }
 
@@ -64,22 +339,300 @@ async fn main() {
             name,
             git,
             hash,
+            dependencies,
+            aliases,
             wasm,
+            generate,
+            force,
+            wasm_toolchain,
         }) => {
             let tree_sitter_name = format!("{}{}", "tree-sitter-", name);
-            let language = Language::new(tree_sitter_name, git.clone(), hash.clone());
-            add_language_grammar_to_toml(name.clone(), language, file_path.clone());
-            update_language(
-                Some(name.clone()),
-                false,
-                wasm.clone(),
-                file_path.clone(),
+            let mut language = Language::new(tree_sitter_name, git.clone(), hash.clone());
+            language.dependencies = dependencies.clone();
+            language.aliases = aliases.clone();
+            if !add_language_grammar_to_toml(name.clone(), language, file_path.clone()) {
+                std::process::exit(1);
+            }
+            let options = FetchOptions {
+                wasm: *wasm,
+                generate: *generate,
+                force: *force,
+                wasm_toolchain: *wasm_toolchain,
+            };
+            let passed =
+                update_language(Some(name.clone()), false, options, file_path.clone(), dir).await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Fetch {
+            name,
+            all,
+            wasm,
+            generate,
+            force,
+            wasm_toolchain,
+        }) => {
+            let options = FetchOptions {
+                wasm: *wasm,
+                generate: *generate,
+                force: *force,
+                wasm_toolchain: *wasm_toolchain,
+            };
+            let passed = update_language(name.clone(), *all, options, file_path, dir).await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Prune { name, all }) => {
+            let passed = prune_languages(name.clone(), *all, file_path, dir);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Check) => {
+            let diagnostics = match check_languages_toml(&file_path) {
+                Ok(diagnostics) => diagnostics,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            for diagnostic in &diagnostics {
+                eprintln!("{}:{}", file_path.display(), diagnostic);
+            }
+            if !diagnostics.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::PatchDiff { name, output }) => {
+            match language_patch_diff(name, &file_path, &dir, output.as_deref()) {
+                Ok(patch) => match output {
+                    Some(output) => {
+                        if let Some(parent) = output.parent() {
+                            fs::create_dir_all(parent).expect("Failed to create patch directory");
+                        }
+                        fs::write(output, patch).expect("Failed to write patch");
+                    }
+                    None => print!("{}", patch),
+                },
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Licenses { notices, summary }) => {
+            let passed = report_licenses(file_path, dir, notices.clone(), summary.clone());
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Sbom {
+            wasm_directory,
+            output,
+        }) => {
+            let Some(sbom) = generate_sbom(file_path, dir, wasm_directory.clone()) else {
+                std::process::exit(1);
+            };
+            match output {
+                Some(output) => fs::write(output, sbom).expect("Failed to write SBOM"),
+                None => print!("{}", sbom),
+            }
+        }
+        Some(Commands::StaticLib {
+            name,
+            all,
+            output,
+            per_language,
+        }) => {
+            let passed = build_static_libraries(
+                name.clone(),
+                *all,
+                file_path,
                 dir,
+                output.clone(),
+                *per_language,
             )
             .await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Bindings {
+            name,
+            all,
+            tree_sitter_version,
+        }) => {
+            let passed = regenerate_bindings(
+                name.clone(),
+                *all,
+                file_path,
+                dir,
+                tree_sitter_version.clone(),
+                cli.query_flavor.clone(),
+            );
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::RustCrate {
+            output,
+            crate_name,
+            tree_sitter_version,
+        }) => {
+            let written = write_rust_crate(
+                file_path,
+                dir,
+                output.clone(),
+                crate_name.clone(),
+                tree_sitter_version.clone(),
+                cli.query_flavor.clone(),
+            );
+            if !written {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Bundle {
+            wasm_directory,
+            output,
+            version,
+            archive,
+        }) => {
+            let passed = bundle_grammars(
+                file_path,
+                dir,
+                wasm_directory.clone(),
+                output.clone(),
+                version.clone(),
+                *archive,
+                cli.query_flavor.clone(),
+            );
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::LintQueries { name, all }) => {
+            let passed = lint_queries(name.clone(), *all, file_path, dir);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Queries {
+            name,
+            all,
+            output,
+            canonical_captures,
+        }) => {
+            let passed = export_queries(
+                name.clone(),
+                *all,
+                file_path,
+                dir,
+                output.clone(),
+                cli.query_flavor.clone(),
+                *canonical_captures,
+            );
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Captures { name, all }) => {
+            let passed = list_captures(name.clone(), *all, file_path, dir);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Injections) => {
+            let passed = report_injections(file_path, dir);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::NodeIndex {
+            output,
+            node,
+            field,
+        }) => {
+            let passed =
+                index_node_types(file_path, dir, output.clone(), node.clone(), field.clone());
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Test { name, all, builtin }) => {
+            let passed = test_grammars(name.clone(), *all, file_path, dir, *builtin).await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Snapshot {
+            name,
+            all,
+            output,
+            update,
+            diff,
+        }) => {
+            let passed = snapshot_grammars(
+                name.clone(),
+                *all,
+                file_path,
+                dir,
+                output.clone(),
+                *update,
+                *diff,
+            )
+            .await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Bench {
+            name,
+            all,
+            input,
+            iterations,
+            output,
+        }) => {
+            let passed = bench_grammars(
+                name.clone(),
+                *all,
+                file_path,
+                dir,
+                input.clone(),
+                *iterations,
+                output.clone(),
+            )
+            .await;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Codegen { target }) => match target {
+            CodegenTarget::Rust { name, all, output } => {
+                let passed = codegen_rust(name.clone(), *all, file_path, dir, output.clone());
+                if !passed {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Some(Commands::List) => {
+            let passed = list_languages(file_path, dir);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Verify { regenerate }) => {
+            let passed = verify_grammars(file_path, dir, *regenerate).await;
+            if !passed {
+                std::process::exit(1);
+            }
         }
-        Some(Commands::Fetch { name, all, wasm }) => {
-            update_language(name.clone(), all.clone(), wasm.clone(), file_path, dir).await;
+        Some(Commands::Validate { wasm_directory }) => {
+            let passed = validate_artifacts(file_path, dir, wasm_directory.clone());
+            if !passed {
+                std::process::exit(1);
+            }
         }
         None => {}
     }
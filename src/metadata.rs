@@ -0,0 +1,480 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::build::grammar_symbol_name;
+use crate::Language;
+
+/// Query kinds a grammar's metadata can list files for.
+pub const METADATA_QUERIES: [&str; 4] = ["highlights", "injections", "locals", "tags"];
+
+/// Query kinds grammars ship variants of for different editors, e.g. `helix-highlights.scm`.
+pub const FLAVORED_QUERIES: [&str; 7] = [
+    "highlights",
+    "injections",
+    "locals",
+    "tags",
+    "folds",
+    "indents",
+    "textobjects",
+];
+
+/// A value in grammar metadata that may be given either as a single string or as a list.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Vec<String> {
+    fn from(value: OneOrMany) -> Self {
+        match value {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<OneOrMany> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.map(Vec::from).unwrap_or_default())
+}
+
+/// The description of a single grammar in the `tree-sitter` section of its `package.json`, or
+/// the `grammars` section of its `tree-sitter.json`.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GrammarMetadata {
+    /// Directory of the grammar, relative to the repository, for repositories with several
+    pub path: Option<String>,
+    pub scope: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub file_types: Vec<String>,
+    pub injection_regex: Option<String>,
+    pub first_line_regex: Option<String>,
+    /// Query files, relative to the repository
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub highlights: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub injections: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub locals: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub tags: Vec<String>,
+}
+
+impl GrammarMetadata {
+    /// Whether this describes the grammar at the root of the repository.
+    pub fn is_root(&self) -> bool {
+        matches!(self.path.as_deref(), None | Some("") | Some("."))
+    }
+
+    /// Query files of the given kind, e.g. `highlights`.
+    pub fn queries(&self, kind: &str) -> &[String] {
+        match kind {
+            "highlights" => &self.highlights,
+            "injections" => &self.injections,
+            "locals" => &self.locals,
+            "tags" => &self.tags,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+    #[serde(default, rename = "tree-sitter")]
+    tree_sitter: Vec<GrammarMetadata>,
+}
+
+#[derive(Deserialize)]
+struct TreeSitterJson {
+    #[serde(default)]
+    grammars: Vec<GrammarMetadata>,
+}
+
+/// Reads the metadata of every grammar in a repository, preferring `tree-sitter.json` over the
+/// `tree-sitter` section of `package.json`. Returns an empty list if neither describes any.
+pub fn read_grammar_metadata(grammar_directory: &Path) -> Vec<GrammarMetadata> {
+    let tree_sitter_json = fs::read_to_string(grammar_directory.join("tree-sitter.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<TreeSitterJson>(&contents).ok())
+        .map(|config| config.grammars)
+        .unwrap_or_default();
+    if !tree_sitter_json.is_empty() {
+        return tree_sitter_json;
+    }
+
+    fs::read_to_string(grammar_directory.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<PackageJson>(&contents).ok())
+        .map(|package| package.tree_sitter)
+        .unwrap_or_default()
+}
+
+/// Returns the metadata of the grammar at the root of a repository, falling back to the first
+/// grammar described.
+pub fn root_grammar_metadata(grammar_directory: &Path) -> Option<GrammarMetadata> {
+    let grammars = read_grammar_metadata(grammar_directory);
+    grammars
+        .iter()
+        .find(|grammar| grammar.is_root())
+        .or(grammars.first())
+        .cloned()
+}
+
+/// Resolves a query file listed in a grammar's metadata. Paths into `node_modules/`, which
+/// grammars like TypeScript use to extend the queries of the grammar they are based on, resolve
+/// to the vendored grammar next to this one when the dependency is not installed.
+pub fn resolve_query_path(grammar_directory: &Path, path: &str) -> Option<PathBuf> {
+    let local = grammar_directory.join(path);
+    if local.is_file() {
+        return Some(local);
+    }
+    let sibling = grammar_directory
+        .parent()?
+        .join(path.strip_prefix("node_modules/")?);
+    sibling.is_file().then_some(sibling)
+}
+
+/// Returns the variant of a query file for a flavor, e.g. `queries/helix-highlights.scm` for
+/// `queries/highlights.scm`, which replaces it, if the grammar has one. Files that are variants
+/// themselves have none.
+fn flavor_variant(path: &Path, flavor: &str) -> Option<PathBuf> {
+    let kind = path.file_stem()?.to_str()?;
+    let variant = path.with_file_name(format!("{}-{}.scm", flavor, kind));
+    (FLAVORED_QUERIES.contains(&kind) && variant.is_file()).then_some(variant)
+}
+
+/// Returns the addition to a query file for a flavor, e.g. `queries/highlights-jsx.scm` for
+/// `queries/highlights.scm`, which extends it, if the grammar has one.
+fn flavor_addition(path: &Path, flavor: &str) -> Option<PathBuf> {
+    let kind = path.file_stem()?.to_str()?;
+    let addition = path.with_file_name(format!("{}-{}.scm", kind, flavor));
+    (FLAVORED_QUERIES.contains(&kind) && addition.is_file()).then_some(addition)
+}
+
+/// Applies a query flavor to the files of a kind of query, keeping their order: each file is
+/// replaced by its variant and followed by its addition for the flavor, where the grammar has
+/// them. Without any files, the variant or addition in `queries/` is used on its own, so a flavor
+/// can ship kinds of queries the grammar has no default of, like `nova-folds.scm`. A file listed
+/// again is only kept once.
+pub fn flavored_query_paths(
+    grammar_directory: &Path,
+    paths: Vec<PathBuf>,
+    kind: &str,
+    flavor: Option<&str>,
+) -> Vec<PathBuf> {
+    let mut flavored: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let variant = flavor.and_then(|flavor| flavor_variant(&path, flavor));
+        let addition = flavor.and_then(|flavor| flavor_addition(&path, flavor));
+        for path in [variant.unwrap_or(path)].into_iter().chain(addition) {
+            if !flavored.contains(&path) {
+                flavored.push(path);
+            }
+        }
+    }
+    if let Some(flavor) = flavor.filter(|_| flavored.is_empty()) {
+        let default_path = grammar_directory.join(format!("queries/{}.scm", kind));
+        flavored.extend(
+            flavor_variant(&default_path, flavor)
+                .or_else(|| flavor_addition(&default_path, flavor)),
+        );
+    }
+    flavored
+}
+
+/// Returns the flavors of queries a grammar ships, named by the prefix or suffix of their
+/// variants, e.g. `helix` for `helix-highlights.scm` and `jsx` for `highlights-jsx.scm`.
+pub fn query_flavors(language: &Language, grammar_directory: &Path) -> BTreeSet<String> {
+    let mut flavors = BTreeSet::new();
+    for file in grammar_query_files(language, grammar_directory) {
+        let Some(stem) = Path::new(&file).file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        for kind in FLAVORED_QUERIES {
+            let flavor = stem
+                .strip_suffix(kind)
+                .and_then(|flavor| flavor.strip_suffix('-'))
+                .or_else(|| stem.strip_prefix(kind)?.strip_prefix('-'));
+            if let Some(flavor) = flavor.filter(|flavor| !flavor.is_empty()) {
+                flavors.insert(flavor.to_string());
+            }
+        }
+    }
+    flavors
+}
+
+/// Returns the `.scm` files in the `queries/` directories of a repository and of each of its
+/// grammars, relative to the repository.
+pub fn grammar_query_files(language: &Language, grammar_directory: &Path) -> Vec<String> {
+    let mut paths: Vec<String> = grammar_units(language, grammar_directory)
+        .into_iter()
+        .map(|unit| unit.path)
+        .collect();
+    if !paths.contains(&String::new()) {
+        paths.insert(0, String::new());
+    }
+    paths
+        .iter()
+        .flat_map(|path| query_files(grammar_directory, path))
+        .collect()
+}
+
+/// Chooses the query flavor of a language: the given one, e.g. from `--query-flavor`, or else its
+/// `query_flavor` in the languages file. A flavor the grammar has no variants of is reported and
+/// its default queries are used instead.
+pub fn select_query_flavor(
+    language: &Language,
+    grammar_directory: &Path,
+    flavor: Option<&str>,
+) -> Option<String> {
+    let flavor = flavor.or(language.query_flavor.as_deref())?;
+    let flavors = query_flavors(language, grammar_directory);
+    if flavors.contains(flavor) {
+        return Some(flavor.to_string());
+    }
+    let available = match flavors.is_empty() {
+        true => "none".to_string(),
+        false => flavors.into_iter().collect::<Vec<_>>().join(", "),
+    };
+    eprintln!(
+        "Warning: {} has no '{}' queries (available flavors: {}), using its default queries",
+        language.name, flavor, available
+    );
+    None
+}
+
+/// Reads the query files of a kind listed in a grammar's metadata, or `queries/<kind>.scm` if it
+/// lists none, concatenated in order, with `flavor` applied as described in
+/// `flavored_query_paths`. Files that cannot be resolved are skipped.
+pub fn read_query(
+    grammar_directory: &Path,
+    grammar: &GrammarMetadata,
+    kind: &str,
+    flavor: Option<&str>,
+) -> Option<String> {
+    let default_path = [format!("queries/{}.scm", kind)];
+    let paths = match grammar.queries(kind) {
+        [] => &default_path[..],
+        paths => paths,
+    };
+    let resolved: Vec<PathBuf> = paths
+        .iter()
+        .filter_map(|path| resolve_query_path(grammar_directory, path))
+        .collect();
+    let resolved = flavored_query_paths(grammar_directory, resolved, kind, flavor);
+    let sources: Vec<String> = resolved
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .collect();
+    if sources.is_empty() {
+        return None;
+    }
+    Some(sources.join("\n"))
+}
+
+/// A grammar within a repository: its directory relative to the repository, which is empty for
+/// the grammar at the root, and the name of its entry point without `tree_sitter_`.
+pub struct GrammarUnit {
+    pub path: String,
+    pub symbol: String,
+}
+
+impl GrammarUnit {
+    /// The `src` directory of the grammar, relative to the repository.
+    pub fn src(&self) -> String {
+        match self.path.as_str() {
+            "" => "src".to_string(),
+            path => format!("{}/src", path),
+        }
+    }
+}
+
+/// Returns the grammars of a repository: the one at its root, or for repositories with several,
+/// every subdirectory, or subdirectory of `grammars/`, with a `src` directory.
+pub fn grammar_units(language: &Language, grammar_directory: &Path) -> Vec<GrammarUnit> {
+    if grammar_directory.join("src").is_dir() {
+        return vec![GrammarUnit {
+            path: String::new(),
+            symbol: grammar_symbol_name(language, grammar_directory),
+        }];
+    }
+
+    let mut units = Vec::new();
+    for parent in ["", "grammars"] {
+        let Ok(entries) = fs::read_dir(grammar_directory.join(parent)) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let src = path.join("src");
+            if !src.join("grammar.json").is_file() && !src.join("parser.c").is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            // grammars are named in their grammar.json, falling back to their directory
+            let symbol = fs::read_to_string(src.join("grammar.json"))
+                .ok()
+                .and_then(|grammar| serde_json::from_str::<serde_json::Value>(&grammar).ok())
+                .and_then(|grammar| grammar["name"].as_str().map(|name| name.to_string()))
+                .unwrap_or_else(|| name.replace(['-', '.'], "_"));
+            units.push(GrammarUnit {
+                path: match parent {
+                    "" => name,
+                    parent => format!("{}/{}", parent, name),
+                },
+                symbol,
+            });
+        }
+    }
+    units.sort_by(|a, b| a.path.cmp(&b.path));
+    units
+}
+
+/// Returns the grammar of a repository that parses the language itself: the one at its root, or
+/// for repositories with several, the one named after the language, e.g. `typescript` rather
+/// than `tsx`, falling back to the first.
+pub fn language_grammar_unit(
+    language_name: &str,
+    language: &Language,
+    grammar_directory: &Path,
+) -> Option<GrammarUnit> {
+    let mut units = grammar_units(language, grammar_directory);
+    let symbol = language_name.replace('-', "_");
+    match units.iter().position(|unit| unit.symbol == symbol) {
+        Some(index) => Some(units.swap_remove(index)),
+        None => units.into_iter().next(),
+    }
+}
+
+/// Returns the `.scm` files below the `queries/` directory of the grammar at `path`, relative to
+/// the repository, in file name order.
+pub fn query_files(grammar_directory: &Path, path: &str) -> Vec<String> {
+    fn collect(directory: &Path, relative: &str, files: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(directory) else {
+            return;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let relative = format!("{}/{}", relative, name);
+            let path = entry.path();
+            if path.is_dir() {
+                collect(&path, &relative, files);
+            } else if name.ends_with(".scm") {
+                files.push(relative);
+            }
+        }
+    }
+
+    let queries = match path {
+        "" => "queries".to_string(),
+        path => format!("{}/queries", path),
+    };
+    let mut files = Vec::new();
+    collect(&grammar_directory.join(&queries), &queries, &mut files);
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flavored_grammar(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files = [
+            ("src/parser.c", ""),
+            ("queries/highlights.scm", "(identifier) @variable"),
+            (
+                "queries/helix-highlights.scm",
+                "(identifier) @variable.other",
+            ),
+            ("queries/highlights-jsx.scm", "(jsx_element) @tag"),
+            ("queries/injections.scm", "(comment) @injection.content"),
+            ("queries/nova-folds.scm", "(block) @fold"),
+        ];
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn selects_a_flavor_the_grammar_has() {
+        let root = flavored_grammar("flavor-selection");
+        let mut language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        let flavors = query_flavors(&language, &root);
+        let requested = select_query_flavor(&language, &root, Some("helix"));
+        let unknown = select_query_flavor(&language, &root, Some("zed"));
+        let unset = select_query_flavor(&language, &root, None);
+        language.query_flavor = Some("jsx".to_string());
+        let configured = select_query_flavor(&language, &root, None);
+        let overridden = select_query_flavor(&language, &root, Some("nova"));
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(
+            flavors.into_iter().collect::<Vec<_>>(),
+            ["helix", "jsx", "nova"]
+        );
+        assert_eq!(requested.as_deref(), Some("helix"));
+        assert_eq!(unknown, None);
+        assert_eq!(unset, None);
+        assert_eq!(configured.as_deref(), Some("jsx"));
+        assert_eq!(overridden.as_deref(), Some("nova"));
+    }
+
+    #[test]
+    fn replaces_variants_and_appends_additions() {
+        let root = flavored_grammar("flavor-paths");
+        let queries = root.join("queries");
+        let flavored = |kind: &str, flavor: Option<&str>| -> Vec<PathBuf> {
+            let default_path = queries.join(format!("{}.scm", kind));
+            let paths = match default_path.is_file() {
+                true => vec![default_path],
+                false => Vec::new(),
+            };
+            flavored_query_paths(&root, paths, kind, flavor)
+        };
+        let highlights = flavored("highlights", None);
+        let helix = flavored("highlights", Some("helix"));
+        let jsx = flavored("highlights", Some("jsx"));
+        let injections = flavored("injections", Some("helix"));
+        let nova = flavored("folds", Some("nova"));
+        let no_folds = flavored("folds", Some("helix"));
+        let query = read_query(
+            &root,
+            &GrammarMetadata::default(),
+            "highlights",
+            Some("jsx"),
+        );
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(highlights, [queries.join("highlights.scm")]);
+        assert_eq!(helix, [queries.join("helix-highlights.scm")]);
+        assert_eq!(
+            jsx,
+            [
+                queries.join("highlights.scm"),
+                queries.join("highlights-jsx.scm")
+            ]
+        );
+        assert_eq!(injections, [queries.join("injections.scm")]);
+        assert_eq!(nova, [queries.join("nova-folds.scm")]);
+        assert_eq!(no_folds, Vec::<PathBuf>::new());
+        assert_eq!(
+            query.as_deref(),
+            Some("(identifier) @variable\n(jsx_element) @tag")
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::build::find_executable;
+use crate::metadata::GrammarUnit;
+use crate::Language;
+
+/// Name of the archive holding every grammar when not building one archive per language.
+pub const STATIC_LIBRARY_NAME: &str = "libtree-sitter-grammars.a";
+
+/// Name of the header declaring the entry point of every grammar.
+pub const HEADER_NAME: &str = "tree-sitter-grammars.h";
+
+/// The programs used to compile grammars into static libraries, taken from the `CC`, `CXX`,
+/// `LD`, `OBJCOPY` and `AR` environment variables when set.
+#[derive(Debug, Clone)]
+pub struct NativeToolchain {
+    pub cc: PathBuf,
+    pub cxx: PathBuf,
+    pub ld: PathBuf,
+    pub objcopy: PathBuf,
+    pub ar: PathBuf,
+}
+
+/// Finds the C compiler, C++ compiler, linker, objcopy and archiver on the `PATH`.
+pub fn detect_native_toolchain() -> Result<NativeToolchain, String> {
+    let program = |variable: &str, names: &[&str]| {
+        if let Some(program) = env::var_os(variable) {
+            return Ok(PathBuf::from(program));
+        }
+        names
+            .iter()
+            .find_map(|name| find_executable(name))
+            .ok_or_else(|| {
+                format!(
+                    "No {} found, install one of {} or set {}",
+                    variable.to_lowercase(),
+                    names.join(", "),
+                    variable
+                )
+            })
+    };
+
+    Ok(NativeToolchain {
+        cc: program("CC", &["cc", "gcc", "clang"])?,
+        cxx: program("CXX", &["c++", "g++", "clang++"])?,
+        ld: program("LD", &["ld", "ld.lld"])?,
+        objcopy: program("OBJCOPY", &["objcopy", "llvm-objcopy"])?,
+        ar: program("AR", &["ar", "llvm-ar"])?,
+    })
+}
+
+/// Compiles the parser and scanner of one grammar of a repository into a single relocatable
+/// object in which every symbol except `tree_sitter_<name>` is local, so the helper functions of
+/// different grammars' scanners cannot collide when they are linked into the same program.
+/// Returns the entry point.
+pub async fn build_native_object(
+    toolchain: &NativeToolchain,
+    language: &Language,
+    grammar_directory: &Path,
+    unit: &GrammarUnit,
+    object: &Path,
+) -> Result<String, String> {
+    let grammar_directory = std::path::absolute(grammar_directory.join(&unit.path))
+        .map_err(|e| format!("Invalid path {}: {}", grammar_directory.display(), e))?;
+    if !grammar_directory.join("src/parser.c").is_file() {
+        return Err(format!(
+            "No {}/parser.c to compile for language: {}",
+            unit.src(),
+            language.name
+        ));
+    }
+    let symbol = format!("tree_sitter_{}", unit.symbol);
+
+    let object = std::path::absolute(object)
+        .map_err(|e| format!("Invalid output path {}: {}", object.display(), e))?;
+    let objects_directory = object.with_extension("objects");
+    let objects =
+        compile_sources(toolchain, language, &grammar_directory, &objects_directory).await?;
+
+    let mut arguments = vec![
+        "-r".to_string(),
+        "-o".to_string(),
+        object.display().to_string(),
+    ];
+    arguments.extend(objects);
+    run(&toolchain.ld, &grammar_directory, &arguments, language).await?;
+
+    let arguments = vec![
+        format!("--keep-global-symbol={}", symbol),
+        object.display().to_string(),
+    ];
+    run(&toolchain.objcopy, &grammar_directory, &arguments, language).await?;
+
+    fs::remove_dir_all(&objects_directory)
+        .map_err(|e| format!("Failed to remove {}: {}", objects_directory.display(), e))?;
+    Ok(symbol)
+}
+
+/// Compiles a grammar's parser and scanner into a shared library that can be loaded at runtime,
+/// e.g. to parse with the grammar without the `tree-sitter` CLI.
+pub async fn build_shared_library(
+    toolchain: &NativeToolchain,
+    language: &Language,
+    grammar_directory: &Path,
+    library: &Path,
+) -> Result<(), String> {
+    let grammar_directory = std::path::absolute(grammar_directory)
+        .map_err(|e| format!("Invalid path {}: {}", grammar_directory.display(), e))?;
+    if !grammar_directory.join("src/parser.c").is_file() {
+        return Err(format!(
+            "No src/parser.c to compile for language: {}",
+            language.name
+        ));
+    }
+    let library = std::path::absolute(library)
+        .map_err(|e| format!("Invalid output path {}: {}", library.display(), e))?;
+    let objects_directory = library.with_extension("objects");
+    let objects =
+        compile_sources(toolchain, language, &grammar_directory, &objects_directory).await?;
+
+    // a C++ scanner needs the C++ standard library
+    let linker = match grammar_directory.join("src/scanner.cc").is_file() {
+        true => &toolchain.cxx,
+        false => &toolchain.cc,
+    };
+    let mut arguments = vec![
+        "-shared".to_string(),
+        "-o".to_string(),
+        library.display().to_string(),
+    ];
+    arguments.extend(objects);
+    run(linker, &grammar_directory, &arguments, language).await?;
+
+    fs::remove_dir_all(&objects_directory)
+        .map_err(|e| format!("Failed to remove {}: {}", objects_directory.display(), e))
+}
+
+/// Compiles the parser and scanner of a grammar to position independent objects in
+/// `objects_directory`, returning their paths.
+async fn compile_sources(
+    toolchain: &NativeToolchain,
+    language: &Language,
+    grammar_directory: &Path,
+    objects_directory: &Path,
+) -> Result<Vec<String>, String> {
+    fs::create_dir_all(objects_directory)
+        .map_err(|e| format!("Failed to create {}: {}", objects_directory.display(), e))?;
+
+    let mut objects = Vec::new();
+    for (source, compiler, standard) in [
+        ("src/parser.c", &toolchain.cc, "-std=c11"),
+        ("src/scanner.c", &toolchain.cc, "-std=c11"),
+        ("src/scanner.cc", &toolchain.cxx, "-std=c++14"),
+    ] {
+        if !grammar_directory.join(source).is_file() {
+            continue;
+        }
+        let output = objects_directory.join(format!(
+            "{}.o",
+            Path::new(source).file_name().unwrap().to_string_lossy()
+        ));
+        let arguments = vec![
+            "-c".to_string(),
+            "-O2".to_string(),
+            "-fPIC".to_string(),
+            standard.to_string(),
+            "-I".to_string(),
+            "src".to_string(),
+            source.to_string(),
+            "-o".to_string(),
+            output.display().to_string(),
+        ];
+        run(compiler, grammar_directory, &arguments, language).await?;
+        objects.push(output.display().to_string());
+    }
+    Ok(objects)
+}
+
+/// Replaces `archive` with a static library containing the given objects.
+pub async fn create_archive(
+    toolchain: &NativeToolchain,
+    archive: &Path,
+    objects: &[PathBuf],
+) -> Result<(), String> {
+    if archive.exists() {
+        fs::remove_file(archive)
+            .map_err(|e| format!("Failed to remove {}: {}", archive.display(), e))?;
+    }
+    let mut command = tokio::process::Command::new(&toolchain.ar);
+    command.arg("rcs").arg(archive).args(objects);
+    match command.status().await {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!(
+            "Command exited with status: {}, creating {}",
+            status,
+            archive.display()
+        )),
+        Err(e) => Err(format!(
+            "Failed to execute {}: {}",
+            toolchain.ar.display(),
+            e
+        )),
+    }
+}
+
+/// Generates a header declaring the entry point of every grammar, keyed by language name.
+pub fn grammars_header(symbols: &BTreeMap<String, String>) -> String {
+    let mut header = String::from(
+        "// Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars static-lib`.\n\n\
+         #ifndef TREE_SITTER_GRAMMARS_H_\n\
+         #define TREE_SITTER_GRAMMARS_H_\n\n\
+         typedef struct TSLanguage TSLanguage;\n\n\
+         #ifdef __cplusplus\n\
+         extern \"C\" {\n\
+         #endif\n\n",
+    );
+    for (language_name, symbol) in symbols {
+        let _ = writeln!(header, "// {}", language_name);
+        let _ = writeln!(header, "const TSLanguage *{}(void);", symbol);
+    }
+    header.push_str(
+        "\n#ifdef __cplusplus\n\
+         }\n\
+         #endif\n\n\
+         #endif // TREE_SITTER_GRAMMARS_H_\n",
+    );
+    header
+}
+
+async fn run(
+    program: &Path,
+    directory: &Path,
+    arguments: &[String],
+    language: &Language,
+) -> Result<(), String> {
+    let command = format!("{} {}", program.display(), arguments.join(" "));
+    let status = tokio::process::Command::new(program)
+        .current_dir(directory)
+        .args(arguments)
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!(
+            "Command exited with status: {}, for language: {}: {}",
+            status, language.name, command
+        )),
+        Err(e) => Err(format!(
+            "Failed to execute command: {}, for language: {}: {}",
+            e, language.name, command
+        )),
+    }
+}
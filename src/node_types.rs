@@ -0,0 +1,367 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::grammar_units;
+use crate::Language;
+
+/// A reference to a node type, as listed in the fields, children and subtypes of another.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeTypeRef {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub named: bool,
+}
+
+/// The node types a field or the unnamed children of a node can hold.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FieldInfo {
+    pub multiple: bool,
+    pub required: bool,
+    pub types: Vec<NodeTypeRef>,
+}
+
+/// An entry of a grammar's generated `src/node-types.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeType {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub named: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, FieldInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<FieldInfo>,
+    /// Set for supertypes, which are never nodes in a tree but stand for any of these
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtypes: Vec<NodeTypeRef>,
+}
+
+impl NodeType {
+    pub fn is_supertype(&self) -> bool {
+        !self.subtypes.is_empty()
+    }
+}
+
+/// Reads the `node-types.json` generated next to a grammar's parser. Entries that are not node
+/// types, like the `{"@generated": true}` marker some generators append, are skipped.
+pub fn read_node_types(path: &Path) -> Result<Vec<NodeType>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    entries
+        .into_iter()
+        .filter(|entry| entry.get("type").is_some())
+        .map(|entry| {
+            serde_json::from_value(entry).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+/// Reads the visible rules a grammar's `src/grammar.json` lists as extras, e.g. `comment`.
+/// Older versions of the `tree-sitter` CLI left these out of `node-types.json`, although queries
+/// can match them.
+pub fn read_extra_node_types(grammar_json: &Path) -> Vec<String> {
+    let Some(grammar) = fs::read_to_string(grammar_json)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+    else {
+        return Vec::new();
+    };
+    grammar["extras"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|extra| extra["type"] == "SYMBOL")
+        .filter_map(|extra| extra["name"].as_str())
+        .filter(|name| !name.starts_with('_'))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// The names a query may refer to in one or more grammars.
+#[derive(Debug, Clone, Default)]
+pub struct NodeTypeNames {
+    pub named: BTreeSet<String>,
+    pub anonymous: BTreeSet<String>,
+    pub fields: BTreeSet<String>,
+    pub supertypes: BTreeSet<String>,
+    /// The fields of every node type that has any
+    pub node_fields: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl NodeTypeNames {
+    /// Adds the names of a grammar's node types, including the ones only referenced by others.
+    pub fn add(&mut self, node_types: &[NodeType]) {
+        for node_type in node_types {
+            self.add_ref(&node_type.kind, node_type.named);
+            if node_type.is_supertype() {
+                self.supertypes.insert(node_type.kind.clone());
+            }
+            for (field, info) in &node_type.fields {
+                self.fields.insert(field.clone());
+                self.node_fields
+                    .entry(node_type.kind.clone())
+                    .or_default()
+                    .insert(field.clone());
+                for child in &info.types {
+                    self.add_ref(&child.kind, child.named);
+                }
+            }
+            let references = node_type.children.iter().flat_map(|info| &info.types);
+            for child in references.chain(&node_type.subtypes) {
+                self.add_ref(&child.kind, child.named);
+            }
+        }
+    }
+
+    fn add_ref(&mut self, kind: &str, named: bool) {
+        match named {
+            true => self.named.insert(kind.to_string()),
+            false => self.anonymous.insert(kind.to_string()),
+        };
+    }
+}
+
+/// A grammar in the node types index: the repository of a language, or one of its
+/// subdirectories for repositories with several grammars.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct IndexedGrammar {
+    /// Key of the language in the languages file
+    pub language: String,
+    /// Directory of the grammar in the repository, empty for the one at its root
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+}
+
+/// A named node type of the index, with the grammars that have it and each of its fields.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct IndexedNodeType {
+    /// Grammars with this node type, by name
+    pub grammars: BTreeSet<String>,
+    /// The fields of the node type, with the grammars whose node type has them
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, BTreeSet<String>>,
+    /// The supertypes the node type is a subtype of, with the grammars it is one in
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub supertypes: BTreeMap<String, BTreeSet<String>>,
+    /// For supertypes, their named subtypes, with the grammars they are one in
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub subtypes: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// The named node types of all vendored grammars merged by name, to find which grammars have a
+/// node type, a field or a subtype across languages.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct NodeTypesIndex {
+    /// The indexed grammars, by their name in `grammar.json`, e.g. `tsx`
+    pub grammars: BTreeMap<String, IndexedGrammar>,
+    pub node_types: BTreeMap<String, IndexedNodeType>,
+    /// `node-types.json` files that could not be read
+    #[serde(skip)]
+    pub errors: Vec<String>,
+}
+
+impl NodeTypesIndex {
+    /// Adds the named node types of a grammar, including the ones only listed as a subtype.
+    pub fn add(&mut self, grammar: &str, node_types: &[NodeType]) {
+        for node_type in node_types.iter().filter(|node_type| node_type.named) {
+            let subtypes = node_type.subtypes.iter().filter(|subtype| subtype.named);
+            for subtype in subtypes.clone() {
+                self.entry(&subtype.kind, grammar)
+                    .supertypes
+                    .entry(node_type.kind.clone())
+                    .or_default()
+                    .insert(grammar.to_string());
+            }
+
+            let indexed = self.entry(&node_type.kind, grammar);
+            for field in node_type.fields.keys() {
+                indexed
+                    .fields
+                    .entry(field.clone())
+                    .or_default()
+                    .insert(grammar.to_string());
+            }
+            for subtype in subtypes {
+                indexed
+                    .subtypes
+                    .entry(subtype.kind.clone())
+                    .or_default()
+                    .insert(grammar.to_string());
+            }
+        }
+    }
+
+    /// Returns the grammars with a node type, in name order.
+    pub fn grammars_with_node_type(&self, kind: &str) -> Vec<&str> {
+        self.node_types
+            .get(kind)
+            .into_iter()
+            .flat_map(|indexed| &indexed.grammars)
+            .map(|grammar| grammar.as_str())
+            .collect()
+    }
+
+    /// Returns the grammars whose node type has a field, e.g. the grammars with a
+    /// `function_definition` node with a `name` field, in name order.
+    pub fn grammars_with_field(&self, kind: &str, field: &str) -> Vec<&str> {
+        self.node_types
+            .get(kind)
+            .and_then(|indexed| indexed.fields.get(field))
+            .into_iter()
+            .flatten()
+            .map(|grammar| grammar.as_str())
+            .collect()
+    }
+
+    /// Returns the grammars in which a node type is a subtype of a supertype, in name order.
+    pub fn grammars_with_subtype(&self, supertype: &str, kind: &str) -> Vec<&str> {
+        self.node_types
+            .get(supertype)
+            .and_then(|indexed| indexed.subtypes.get(kind))
+            .into_iter()
+            .flatten()
+            .map(|grammar| grammar.as_str())
+            .collect()
+    }
+
+    fn entry(&mut self, kind: &str, grammar: &str) -> &mut IndexedNodeType {
+        let indexed = self.node_types.entry(kind.to_string()).or_default();
+        indexed.grammars.insert(grammar.to_string());
+        indexed
+    }
+}
+
+/// Indexes the node types of every grammar of the vendored repositories that has a generated
+/// `node-types.json`, including each grammar of repositories with several.
+pub fn node_types_index(
+    languages: &BTreeMap<String, Language>,
+    directory: &Path,
+) -> NodeTypesIndex {
+    let mut index = NodeTypesIndex::default();
+    for (language_name, language) in languages {
+        let grammar_directory = directory.join(&language.name);
+        for unit in grammar_units(language, &grammar_directory) {
+            let node_types_path = grammar_directory.join(unit.src()).join("node-types.json");
+            if !node_types_path.is_file() {
+                continue;
+            }
+            match read_node_types(&node_types_path) {
+                Ok(node_types) => index.add(&unit.symbol, &node_types),
+                Err(e) => {
+                    index.errors.push(e);
+                    continue;
+                }
+            }
+            index.grammars.insert(
+                unit.symbol,
+                IndexedGrammar {
+                    language: language_name.clone(),
+                    path: unit.path,
+                },
+            );
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PYTHON: &str = r#"[
+        {"type": "_statement", "named": true, "subtypes": [
+            {"type": "function_definition", "named": true},
+            {"type": "pass_statement", "named": true}
+        ]},
+        {"type": "function_definition", "named": true, "fields": {
+            "name": {"multiple": false, "required": true, "types": [{"type": "identifier", "named": true}]},
+            "return_type": {"multiple": false, "required": false, "types": [{"type": "type", "named": true}]}
+        }},
+        {"type": "identifier", "named": true},
+        {"type": "def", "named": false}
+    ]"#;
+
+    const C: &str = r#"[
+        {"type": "function_definition", "named": true, "fields": {
+            "declarator": {"multiple": false, "required": true, "types": [{"type": "identifier", "named": true}]}
+        }},
+        {"type": "identifier", "named": true}
+    ]"#;
+
+    #[test]
+    fn finds_grammars_by_node_type_field_and_subtype() {
+        let mut index = NodeTypesIndex::default();
+        index.add(
+            "python",
+            &serde_json::from_str::<Vec<NodeType>>(PYTHON).unwrap(),
+        );
+        index.add("c", &serde_json::from_str::<Vec<NodeType>>(C).unwrap());
+
+        assert_eq!(
+            index.grammars_with_node_type("function_definition"),
+            ["c", "python"]
+        );
+        assert_eq!(index.grammars_with_node_type("def"), Vec::<&str>::new());
+        assert_eq!(
+            index.grammars_with_field("function_definition", "name"),
+            ["python"]
+        );
+        assert_eq!(
+            index.grammars_with_field("function_definition", "declarator"),
+            ["c"]
+        );
+        assert_eq!(
+            index.grammars_with_field("identifier", "name"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            index.grammars_with_subtype("_statement", "pass_statement"),
+            ["python"]
+        );
+        // subtypes are indexed even without an entry of their own
+        assert_eq!(index.grammars_with_node_type("pass_statement"), ["python"]);
+        assert_eq!(
+            index.node_types["function_definition"].supertypes["_statement"],
+            BTreeSet::from(["python".to_string()])
+        );
+    }
+
+    #[test]
+    fn indexes_every_grammar_of_a_repository() {
+        let root = std::env::temp_dir().join(format!("node-index-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let grammar_directory = root.join("tree-sitter-test");
+        // some generators add a marker that is not a node type
+        let c = C.replacen('[', "[{\"@generated\": true},", 1);
+        for (path, contents) in [
+            ("python/src/node-types.json", PYTHON),
+            ("python/src/grammar.json", r#"{"name": "python"}"#),
+            ("c/src/node-types.json", c.as_str()),
+            ("c/src/grammar.json", r#"{"name": "c"}"#),
+            ("broken/src/node-types.json", "{"),
+            ("broken/src/grammar.json", r#"{"name": "broken"}"#),
+        ] {
+            let path = grammar_directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let languages = BTreeMap::from([(
+            "test".to_string(),
+            Language::new("tree-sitter-test".to_string(), String::new(), None),
+        )]);
+
+        let index = node_types_index(&languages, &root);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(index.grammars.keys().collect::<Vec<_>>(), ["c", "python"]);
+        assert_eq!(index.grammars["c"].language, "test");
+        assert_eq!(index.grammars["c"].path, "c");
+        assert_eq!(index.errors.len(), 1);
+        assert_eq!(index.grammars_with_node_type("identifier"), ["c", "python"]);
+    }
+}
@@ -0,0 +1,209 @@
+use git2::build::CheckoutBuilder;
+use git2::ApplyLocation;
+use git2::ApplyOptions;
+use git2::Diff;
+use git2::DiffFormat;
+use git2::DiffOptions;
+use git2::IndexAddOption;
+use git2::Oid;
+use git2::Repository;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::prune::PruneMatcher;
+use crate::Language;
+
+/// Returns the patches to apply to a language after it is fetched, in order. Patches listed in
+/// the `patches` field of the languages file take precedence, otherwise every `*.patch` file in
+/// `patches/<language>/` next to the languages file is applied in file name order.
+pub fn language_patches(
+    language_name: &str,
+    language: &Language,
+    file_path: &Path,
+) -> Vec<PathBuf> {
+    let base_directory = file_path.parent().unwrap_or(Path::new("."));
+    if !language.patches.is_empty() {
+        return language
+            .patches
+            .iter()
+            .map(|patch| base_directory.join(patch))
+            .collect();
+    }
+
+    let patch_directory = base_directory.join("patches").join(language_name);
+    let mut patches: Vec<PathBuf> = match fs::read_dir(&patch_directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "patch")
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    patches.sort();
+    patches
+}
+
+/// Applies patches to the working directory of a freshly cloned grammar repository, stopping at
+/// the first patch that does not apply.
+pub fn apply_patches(repo: &Repository, patches: &[PathBuf]) -> Result<(), String> {
+    for patch in patches {
+        let contents = fs::read(patch)
+            .map_err(|e| format!("Failed to read patch {}: {}", patch.display(), e))?;
+        let diff = Diff::from_buffer(&contents)
+            .map_err(|e| format!("Failed to parse patch {}: {}", patch.display(), e.message()))?;
+
+        // libgit2 reports every file and hunk before applying it, so the last ones seen are
+        // the ones that failed
+        let current_file = RefCell::new(String::new());
+        let current_hunk = RefCell::new(String::new());
+        let mut options = ApplyOptions::new();
+        options.delta_callback(|delta| {
+            if let Some(delta) = delta {
+                let path = delta.new_file().path().or(delta.old_file().path());
+                *current_file.borrow_mut() =
+                    path.map_or(String::new(), |path| path.display().to_string());
+            }
+            true
+        });
+        options.hunk_callback(|hunk| {
+            if let Some(hunk) = hunk {
+                *current_hunk.borrow_mut() = String::from_utf8_lossy(hunk.header())
+                    .trim_end()
+                    .to_string();
+            }
+            true
+        });
+
+        if let Err(e) = repo.apply(&diff, ApplyLocation::WorkDir, Some(&mut options)) {
+            drop(options);
+            return Err(format!(
+                "Failed to apply patch {}: hunk '{}' of {} did not apply ({})",
+                patch.display(),
+                current_hunk.borrow(),
+                current_file.borrow(),
+                e.message()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks out the commit a language is pinned to, updating the working directory as well as
+/// `HEAD`.
+pub fn checkout_pinned_commit(repo: &Repository, commit_hash: &str) -> Result<(), git2::Error> {
+    let oid = Oid::from_str(commit_hash)?;
+    let commit = repo.find_commit(oid)?;
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(oid)
+}
+
+/// Creates a patch containing every local edit made to a vendored grammar beyond its patches,
+/// relative to the upstream commit it was fetched at with those patches applied. The upstream
+/// repository is cloned to a temporary directory. Files the `include` and `exclude` globs of the
+/// grammar prune are left out, as neither their removal nor files like the provisioned
+/// `node_modules` belong in a patch.
+pub fn patch_diff(
+    language: &Language,
+    commit: Option<&str>,
+    patches: &[PathBuf],
+    directory: &Path,
+    prune_rules: (Vec<String>, Vec<String>),
+) -> Result<String, String> {
+    let (include, exclude) = prune_rules;
+    let matcher = PruneMatcher::new(&include, &exclude)?;
+    let grammar_directory = directory.join(&language.name);
+    if !grammar_directory.is_dir() {
+        return Err(format!(
+            "Grammar has not been fetched to {}",
+            grammar_directory.display()
+        ));
+    }
+
+    let upstream_directory = std::env::temp_dir().join(format!(
+        "tree-sitter-grammars-{}-{}",
+        language.name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&upstream_directory);
+
+    let result = upstream_diff(
+        language,
+        commit,
+        patches,
+        &upstream_directory,
+        &grammar_directory,
+        &matcher,
+    );
+    let _ = fs::remove_dir_all(&upstream_directory);
+    result
+}
+
+fn upstream_diff(
+    language: &Language,
+    commit: Option<&str>,
+    patches: &[PathBuf],
+    upstream_directory: &Path,
+    grammar_directory: &Path,
+    matcher: &PruneMatcher,
+) -> Result<String, String> {
+    let repo = Repository::clone(&language.git, upstream_directory)
+        .map_err(|e| format!("Failed to clone {}: {}", language.git, e.message()))?;
+    if let Some(commit_hash) = commit {
+        checkout_pinned_commit(&repo, commit_hash)
+            .map_err(|e| format!("Failed to checkout {}: {}", commit_hash, e.message()))?;
+    }
+    apply_patches(&repo, patches)?;
+
+    // the tree the vendored copy had right after fetching, with its patches applied
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to read upstream index: {}", e.message()))?;
+    index
+        .add_all(["*"], IndexAddOption::FORCE, None)
+        .and_then(|_| index.update_all(["*"], None))
+        .map_err(|e| format!("Failed to stage patched upstream files: {}", e.message()))?;
+    let tree_id = index
+        .write_tree()
+        .map_err(|e| format!("Failed to write patched upstream tree: {}", e.message()))?;
+    let tree = repo
+        .find_tree(tree_id)
+        .map_err(|e| format!("Failed to read patched upstream tree: {}", e.message()))?;
+
+    // compare the upstream tree against the vendored copy instead of the clone
+    repo.set_workdir(grammar_directory, false).map_err(|e| {
+        format!(
+            "Failed to read {}: {}",
+            grammar_directory.display(),
+            e.message()
+        )
+    })?;
+
+    let mut options = DiffOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let diff = repo
+        .diff_tree_to_workdir(Some(&tree), Some(&mut options))
+        .map_err(|e| format!("Failed to diff {}: {}", language.name, e.message()))?;
+
+    let mut patch = Vec::new();
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        let path = delta.new_file().path().or(delta.old_file().path());
+        if !path.is_some_and(|path| matcher.keeps(&path.to_string_lossy().replace('\\', "/"))) {
+            return true;
+        }
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })
+    .map_err(|e| format!("Failed to format patch: {}", e.message()))?;
+
+    Ok(String::from_utf8_lossy(&patch).to_string())
+}
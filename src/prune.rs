@@ -0,0 +1,294 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Language;
+
+/// Files kept from every grammar repository unless `include` is set in the `[prune]` table.
+/// Besides the sources, queries, grammar, license and Rust bindings, this keeps what is needed to
+/// regenerate and test grammars: every script a `grammar.js` may `require()`, e.g.
+/// `grammar/basic.js`, shared grammar code, `package.json` metadata and test corpora.
+pub const DEFAULT_INCLUDE: [&str; 14] = [
+    "**/src/**",
+    "**/queries/**",
+    "**/grammar.js",
+    "**/LICENSE*",
+    "**/COPYING*",
+    "bindings/rust/**",
+    "Cargo.toml",
+    "**/*.js",
+    "common/**",
+    "include/**",
+    "**/package.json",
+    "**/tree-sitter.json",
+    "**/corpus/**",
+    "**/test/**",
+];
+
+/// Files removed from every grammar repository even when they match an include pattern, which
+/// includes the scripts of bindings other than the Rust ones.
+pub const DEFAULT_EXCLUDE: [&str; 5] = [
+    "docs/**",
+    "example/**",
+    "examples/**",
+    "**/node_modules/**",
+    "bindings/**/*.js",
+];
+
+/// The global `[prune]` table of the languages file.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Prune {
+    /// Globs of files to keep, replacing `DEFAULT_INCLUDE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// Globs of files to remove, in addition to `DEFAULT_EXCLUDE`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl Prune {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Combines the global globs with the ones of a single language.
+    pub fn rules_for(&self, language: &Language) -> (Vec<String>, Vec<String>) {
+        let mut include: Vec<String> = match &self.include {
+            Some(include) => include.clone(),
+            None => DEFAULT_INCLUDE
+                .iter()
+                .map(|glob| glob.to_string())
+                .collect(),
+        };
+        include.extend(language.include.iter().cloned());
+
+        let mut exclude: Vec<String> = DEFAULT_EXCLUDE
+            .iter()
+            .map(|glob| glob.to_string())
+            .collect();
+        exclude.extend(self.exclude.iter().cloned());
+        exclude.extend(language.exclude.iter().cloned());
+
+        (include, exclude)
+    }
+}
+
+/// Number of files and bytes removed from a grammar.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl std::ops::AddAssign for PruneReport {
+    fn add_assign(&mut self, other: Self) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+    }
+}
+
+impl std::fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files ({})",
+            self.files,
+            format_size(self.bytes as f64)
+        )
+    }
+}
+
+/// Formats a number of bytes in the largest unit it reaches, e.g. `1.5 MB`.
+pub fn format_size(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Removes every file of a grammar repository that matches none of the `include` globs, or any
+/// of the `exclude` globs, along with the directories left empty. Globs are matched against
+/// paths relative to the repository, with `*` not crossing directory boundaries.
+pub fn prune_grammar(
+    grammar_directory: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<PruneReport, String> {
+    let matcher = PruneMatcher::new(include, exclude)?;
+    let mut report = PruneReport::default();
+    prune_directory(grammar_directory, grammar_directory, &matcher, &mut report)
+        .map_err(|e| format!("Failed to prune {}: {}", grammar_directory.display(), e))?;
+    Ok(report)
+}
+
+/// The `include` and `exclude` globs of a grammar, deciding which of its files pruning keeps.
+pub struct PruneMatcher {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl PruneMatcher {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// Whether pruning keeps the file at `relative_path`, relative to the repository and
+    /// separated by `/`.
+    pub fn keeps(&self, relative_path: &str) -> bool {
+        self.include.is_match(relative_path) && !self.exclude.is_match(relative_path)
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid glob '{}': {}", glob, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn prune_directory(
+    root: &Path,
+    directory: &Path,
+    matcher: &PruneMatcher,
+    report: &mut PruneReport,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            prune_directory(root, &path, matcher, report)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+            continue;
+        }
+
+        let relative_path = path
+            .strip_prefix(root)
+            .expect("Path is inside the grammar directory")
+            .to_string_lossy()
+            .replace('\\', "/");
+        if matcher.keeps(&relative_path) {
+            continue;
+        }
+
+        report.files += 1;
+        report.bytes += entry.metadata()?.len();
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_matcher() -> PruneMatcher {
+        let language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        let (include, exclude) = Prune::default().rules_for(&language);
+        PruneMatcher::new(&include, &exclude).unwrap()
+    }
+
+    #[test]
+    fn keeps_sources_and_the_scripts_grammars_require() {
+        let matcher = default_matcher();
+        for path in [
+            "src/parser.c",
+            "src/tree_sitter/parser.h",
+            "tsx/src/scanner.c",
+            "queries/highlights.scm",
+            "grammar.js",
+            "grammar/basic.js",
+            "lib/util.js",
+            "common/define-grammar.js",
+            "bindings/rust/lib.rs",
+            "LICENSE-MIT",
+            "test/corpus/expressions.txt",
+        ] {
+            assert!(matcher.keeps(path), "{} should be kept", path);
+        }
+    }
+
+    #[test]
+    fn removes_docs_examples_bindings_and_dependencies() {
+        let matcher = default_matcher();
+        for path in [
+            "README.md",
+            "docs/index.md",
+            "examples/grammar/basic.js",
+            "node_modules/tree-sitter-c/grammar.js",
+            "bindings/node/index.js",
+            "bindings/python/tree_sitter_test/__init__.py",
+            "Makefile",
+        ] {
+            assert!(!matcher.keeps(path), "{} should be removed", path);
+        }
+    }
+
+    #[test]
+    fn applies_language_globs() {
+        let mut language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        language.include.push("Makefile".to_string());
+        language.exclude.push("src/*.json".to_string());
+        let (include, exclude) = Prune::default().rules_for(&language);
+        let matcher = PruneMatcher::new(&include, &exclude).unwrap();
+        assert!(matcher.keeps("Makefile"));
+        assert!(!matcher.keeps("src/grammar.json"));
+        // `*` does not cross directory boundaries
+        assert!(matcher.keeps("src/tree_sitter/alloc.json"));
+        assert!(PruneMatcher::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn prunes_a_grammar_directory() {
+        let root = std::env::temp_dir().join(format!("prune-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files = [
+            (
+                "grammar.js",
+                "module.exports = require('./grammar/basic.js');",
+            ),
+            ("grammar/basic.js", "module.exports = grammar({});"),
+            ("src/parser.c", "int x;"),
+            ("docs/index.md", "# Docs"),
+            ("examples/sample.js", "let a = 1;"),
+            ("README.md", "# Test"),
+        ];
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let language = Language::new("tree-sitter-test".to_string(), String::new(), None);
+        let (include, exclude) = Prune::default().rules_for(&language);
+        let report = prune_grammar(&root, &include, &exclude).unwrap();
+        let kept = ["grammar.js", "grammar/basic.js", "src/parser.c"];
+        let result = kept.map(|path| root.join(path).is_file());
+        let removed = ["docs", "examples", "README.md"].map(|path| root.join(path).exists());
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(result, [true; 3]);
+        assert_eq!(removed, [false; 3]);
+        assert_eq!(report.files, 3);
+        assert_eq!(report.bytes, 6 + 10 + 6);
+    }
+}
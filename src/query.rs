@@ -0,0 +1,593 @@
+use std::fmt;
+use std::ops::Range;
+
+/// A name in a query, e.g. a node type, field, capture or predicate, with its byte range in the
+/// query source. Strings are unescaped, their range includes the quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name {
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+/// An argument of a predicate, e.g. `@name`, `"string"` or `local`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PredicateArgument {
+    Capture(Name),
+    String(Name),
+    Identifier(Name),
+}
+
+impl PredicateArgument {
+    pub fn name(&self) -> &Name {
+        match self {
+            PredicateArgument::Capture(name)
+            | PredicateArgument::String(name)
+            | PredicateArgument::Identifier(name) => name,
+        }
+    }
+}
+
+/// A predicate or directive such as `(#eq? @name "self")`, named without its `#` or `.` prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Predicate {
+    pub name: Name,
+    pub arguments: Vec<PredicateArgument>,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternKind {
+    /// `(name children...)`, where the name is `_` for a wildcard matching any named node and a
+    /// supertype is given as `(supertype/name)`. `(MISSING name)` has the missing node as child.
+    Node {
+        name: Name,
+        supertype: Option<Name>,
+        children: Vec<Pattern>,
+        negated_fields: Vec<Name>,
+    },
+    /// `"name"`, an anonymous node.
+    Anonymous(Name),
+    /// `_`, matching any node.
+    Wildcard,
+    /// `[patterns...]`
+    Alternation(Vec<Pattern>),
+    /// `(patterns...)`, a sequence of sibling patterns.
+    Group(Vec<Pattern>),
+    /// `.`
+    Anchor,
+    Predicate(Predicate),
+}
+
+/// A pattern of a query with its field, quantifier and captures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub field: Option<Name>,
+    pub quantifier: Option<char>,
+    pub captures: Vec<Name>,
+    pub span: Range<usize>,
+}
+
+impl Pattern {
+    /// Calls `visit` with this pattern and every pattern nested in it, parents first.
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Pattern)) {
+        visit(self);
+        match &self.kind {
+            PatternKind::Node { children, .. }
+            | PatternKind::Alternation(children)
+            | PatternKind::Group(children) => {
+                for child in children {
+                    child.walk(visit);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A syntax error in a query, located by byte offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Parses the source of a `.scm` query file into its top-level patterns, following the query
+/// syntax of the tree-sitter runtime.
+pub fn parse_query(source: &str) -> Result<Vec<Pattern>, QueryError> {
+    let mut parser = Parser { source, offset: 0 };
+    let mut patterns = Vec::new();
+    loop {
+        parser.skip_trivia();
+        if parser.peek().is_none() {
+            return Ok(patterns);
+        }
+        patterns.push(parser.pattern()?);
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '?' | '!')
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.offset..].chars().nth(1)
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.offset += c.len_utf8();
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, QueryError> {
+        Err(QueryError {
+            offset: self.offset,
+            message: message.into(),
+        })
+    }
+
+    /// Reports a `(` or `[` opened at `offset` that the query never closes.
+    fn unclosed<T>(&self, offset: usize, opening: String) -> Result<T, QueryError> {
+        Err(QueryError {
+            offset,
+            message: format!("unclosed '{}'", opening),
+        })
+    }
+
+    fn unexpected<T>(&self) -> Result<T, QueryError> {
+        match self.peek() {
+            Some(c) => self.error(format!("unexpected '{}'", c)),
+            None => self.error("unexpected end of query"),
+        }
+    }
+
+    /// Skips whitespace and `;` comments.
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else if c == ';' {
+                while !matches!(self.peek(), None | Some('\n')) {
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), QueryError> {
+        self.skip_trivia();
+        if self.peek() != Some(expected) {
+            return match self.peek() {
+                Some(c) => self.error(format!("expected '{}', found '{}'", expected, c)),
+                None => self.error(format!("expected '{}', found end of query", expected)),
+            };
+        }
+        self.bump();
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<Name, QueryError> {
+        let start = self.offset;
+        if !self.peek().is_some_and(is_identifier_start) {
+            return self.unexpected();
+        }
+        while self.peek().is_some_and(is_identifier_char) {
+            self.bump();
+        }
+        Ok(Name {
+            text: self.source[start..self.offset].to_string(),
+            span: start..self.offset,
+        })
+    }
+
+    fn string(&mut self) -> Result<Name, QueryError> {
+        let start = self.offset;
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => {
+                    return Err(QueryError {
+                        offset: start,
+                        message: "unterminated string".to_string(),
+                    })
+                }
+                Some('"') => {
+                    self.bump();
+                    break;
+                }
+                Some('\\') => {
+                    self.bump();
+                    let Some(c) = self.peek() else {
+                        return self.unexpected();
+                    };
+                    text.push(match c {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        '0' => '\0',
+                        c => c,
+                    });
+                    self.bump();
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.bump();
+                }
+            }
+        }
+        Ok(Name {
+            text,
+            span: start..self.offset,
+        })
+    }
+
+    /// Parses a pattern along with its field, quantifier and captures.
+    fn pattern(&mut self) -> Result<Pattern, QueryError> {
+        self.skip_trivia();
+        let start = self.offset;
+
+        let mut field = None;
+        if self.peek().is_some_and(is_identifier_start) && self.peek() != Some('_')
+            || self.peek() == Some('_') && self.peek_second().is_some_and(is_identifier_char)
+        {
+            let name = self.identifier()?;
+            self.skip_trivia();
+            if self.peek() != Some(':') {
+                return Err(QueryError {
+                    offset: name.span.start,
+                    message: format!("unexpected identifier '{}'", name.text),
+                });
+            }
+            self.bump();
+            self.skip_trivia();
+            field = Some(name);
+        }
+
+        let kind = match self.peek() {
+            Some('(') => self.parenthesized()?,
+            Some('[') => {
+                let open = self.offset;
+                self.bump();
+                let mut alternatives = Vec::new();
+                loop {
+                    self.skip_trivia();
+                    match self.peek() {
+                        Some(']') => break,
+                        None => return self.unclosed(open, "[".to_string()),
+                        _ => alternatives.push(self.pattern()?),
+                    }
+                }
+                self.bump();
+                PatternKind::Alternation(alternatives)
+            }
+            Some('"') => PatternKind::Anonymous(self.string()?),
+            Some('_') => {
+                self.bump();
+                PatternKind::Wildcard
+            }
+            Some('.') if field.is_none() => {
+                self.bump();
+                return Ok(Pattern {
+                    kind: PatternKind::Anchor,
+                    field,
+                    quantifier: None,
+                    captures: Vec::new(),
+                    span: start..self.offset,
+                });
+            }
+            _ => return self.unexpected(),
+        };
+        if let PatternKind::Predicate(_) = kind {
+            if let Some(field) = field {
+                return Err(QueryError {
+                    offset: field.span.start,
+                    message: "predicates cannot have a field".to_string(),
+                });
+            }
+            return Ok(Pattern {
+                kind,
+                field,
+                quantifier: None,
+                captures: Vec::new(),
+                span: start..self.offset,
+            });
+        }
+
+        let mut end = self.offset;
+        let mut quantifier = None;
+        let mut captures = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                Some(c @ ('*' | '+' | '?')) if quantifier.is_none() && captures.is_empty() => {
+                    self.bump();
+                    quantifier = Some(c);
+                }
+                Some('@') => {
+                    self.bump();
+                    let mut name = self.identifier()?;
+                    name.span.start -= 1;
+                    captures.push(name);
+                }
+                _ => break,
+            }
+            end = self.offset;
+        }
+        Ok(Pattern {
+            kind,
+            field,
+            quantifier,
+            captures,
+            span: start..end,
+        })
+    }
+
+    /// Parses a node, group or predicate starting at `(`.
+    fn parenthesized(&mut self) -> Result<PatternKind, QueryError> {
+        let open = self.offset;
+        self.bump();
+        self.skip_trivia();
+        match self.peek() {
+            // predicates are written `#name?` or, in older queries, `.name?`
+            Some('#') | Some('.') => self.predicate(),
+            Some(c) if is_identifier_start(c) => {
+                let name = self.identifier()?;
+                let (name, supertype) = if self.peek() == Some('/') {
+                    self.bump();
+                    let subtype = match self.peek() {
+                        Some('"') => self.string()?,
+                        _ => self.identifier()?,
+                    };
+                    (subtype, Some(name))
+                } else {
+                    (name, None)
+                };
+
+                let mut children = Vec::new();
+                let mut negated_fields = Vec::new();
+                loop {
+                    self.skip_trivia();
+                    match self.peek() {
+                        Some(')') => break,
+                        None => return self.unclosed(open, format!("({}", name.text)),
+                        Some('!') => {
+                            self.bump();
+                            negated_fields.push(self.identifier()?);
+                        }
+                        // the node a `MISSING` pattern matches is written as a bare name
+                        Some(c)
+                            if name.text == "MISSING"
+                                && children.is_empty()
+                                && is_identifier_start(c) =>
+                        {
+                            let missing = self.identifier()?;
+                            children.push(Pattern {
+                                span: missing.span.clone(),
+                                kind: PatternKind::Node {
+                                    name: missing,
+                                    supertype: None,
+                                    children: Vec::new(),
+                                    negated_fields: Vec::new(),
+                                },
+                                field: None,
+                                quantifier: None,
+                                captures: Vec::new(),
+                            });
+                        }
+                        _ => children.push(self.pattern()?),
+                    }
+                }
+                self.bump();
+                Ok(PatternKind::Node {
+                    name,
+                    supertype,
+                    children,
+                    negated_fields,
+                })
+            }
+            _ => {
+                let mut children = Vec::new();
+                loop {
+                    self.skip_trivia();
+                    match self.peek() {
+                        Some(')') => break,
+                        None => return self.unclosed(open, "(".to_string()),
+                        _ => children.push(self.pattern()?),
+                    }
+                }
+                self.bump();
+                if children.is_empty() {
+                    return self.error("empty pattern");
+                }
+                Ok(PatternKind::Group(children))
+            }
+        }
+    }
+
+    /// Parses a predicate after its opening `(`.
+    fn predicate(&mut self) -> Result<PatternKind, QueryError> {
+        let start = self.offset - 1;
+        let prefix = self.peek().unwrap_or('#');
+        self.bump();
+        let mut name = self.identifier()?;
+        name.span.start -= 1;
+
+        let mut arguments = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                Some(')') => break,
+                Some('@') => {
+                    self.bump();
+                    let mut capture = self.identifier()?;
+                    capture.span.start -= 1;
+                    arguments.push(PredicateArgument::Capture(capture));
+                }
+                Some('"') => arguments.push(PredicateArgument::String(self.string()?)),
+                Some(c) if is_identifier_start(c) => {
+                    arguments.push(PredicateArgument::Identifier(self.identifier()?))
+                }
+                None => return self.unclosed(start, format!("({}{}", prefix, name.text)),
+                _ => return self.unexpected(),
+            }
+        }
+        self.expect(')')?;
+        Ok(PatternKind::Predicate(Predicate {
+            name,
+            arguments,
+            span: start..self.offset,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(source: &str) -> Pattern {
+        let mut patterns = parse_query(source).unwrap();
+        assert_eq!(patterns.len(), 1);
+        patterns.remove(0)
+    }
+
+    fn children(pattern: &Pattern) -> &[Pattern] {
+        match &pattern.kind {
+            PatternKind::Node { children, .. } | PatternKind::Group(children) => children,
+            kind => panic!("expected children, found {:?}", kind),
+        }
+    }
+
+    fn predicate(pattern: &Pattern) -> &Predicate {
+        match &pattern.kind {
+            PatternKind::Predicate(predicate) => predicate,
+            kind => panic!("expected a predicate, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_predicates() {
+        let source = r#"((identifier) @name (#eq? @name "self") (#set! local))"#;
+        let pattern = parse_one(source);
+        let [node, predicate_pattern, directive] = children(&pattern) else {
+            panic!("expected three patterns in the group");
+        };
+        assert_eq!(node.captures[0].text, "name");
+
+        let eq = predicate(predicate_pattern);
+        assert_eq!(eq.name.text, "eq?");
+        assert_eq!(&source[eq.name.span.clone()], "#eq?");
+        assert_eq!(
+            eq.arguments,
+            vec![
+                PredicateArgument::Capture(Name {
+                    text: "name".to_string(),
+                    span: 26..31,
+                }),
+                PredicateArgument::String(Name {
+                    text: "self".to_string(),
+                    span: 32..38,
+                }),
+            ]
+        );
+        assert!(matches!(
+            predicate(directive).arguments.as_slice(),
+            [PredicateArgument::Identifier(name)] if name.text == "local"
+        ));
+    }
+
+    #[test]
+    fn parses_predicates_with_a_leading_dot() {
+        let pattern = parse_one(
+            r#"(call_expression
+  function: (identifier) @function.builtin
+  (.match? @function.builtin "^(append|cap)$"))"#,
+        );
+        let [function, matches] = children(&pattern) else {
+            panic!("expected two children");
+        };
+        assert_eq!(function.field.as_ref().unwrap().text, "function");
+        assert_eq!(function.captures[0].text, "function.builtin");
+        assert_eq!(predicate(matches).name.text, "match?");
+    }
+
+    #[test]
+    fn parses_anchors() {
+        let pattern = parse_one("(array . (number) @first (_) @last .)");
+        let kinds: Vec<bool> = children(&pattern)
+            .iter()
+            .map(|child| child.kind == PatternKind::Anchor)
+            .collect();
+        assert_eq!(kinds, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn parses_negated_fields() {
+        let pattern = parse_one("(function_definition !parameters body: (block))");
+        let PatternKind::Node {
+            name,
+            negated_fields,
+            children,
+            ..
+        } = &pattern.kind
+        else {
+            panic!("expected a node");
+        };
+        assert_eq!(name.text, "function_definition");
+        assert_eq!(negated_fields[0].text, "parameters");
+        assert_eq!(children[0].field.as_ref().unwrap().text, "body");
+    }
+
+    #[test]
+    fn parses_supertypes_quantifiers_and_alternations() {
+        let pattern = parse_one(r#"[(expression/identifier)* "+"] @operand @other"#);
+        assert_eq!(pattern.captures.len(), 2);
+        let PatternKind::Alternation(alternatives) = &pattern.kind else {
+            panic!("expected an alternation");
+        };
+        assert_eq!(alternatives[0].quantifier, Some('*'));
+        assert!(matches!(
+            &alternatives[0].kind,
+            PatternKind::Node { name, supertype: Some(supertype), .. }
+                if name.text == "identifier" && supertype.text == "expression"
+        ));
+        assert!(matches!(&alternatives[1].kind, PatternKind::Anonymous(name) if name.text == "+"));
+    }
+
+    #[test]
+    fn reports_errors_at_their_offset() {
+        let error = parse_query("(identifier) @name\n(call").unwrap_err();
+        assert_eq!(error.offset, 19);
+        assert_eq!(error.message, "unclosed '(call'");
+
+        let error = parse_query(r#"((identifier) field: (#eq? @a "b"))"#).unwrap_err();
+        assert_eq!(error.offset, 14);
+        assert_eq!(error.message, "predicates cannot have a field");
+
+        let error = parse_query("(call match? @a)").unwrap_err();
+        assert_eq!(error.message, "unexpected identifier 'match?'");
+    }
+}
@@ -0,0 +1,87 @@
+use libloading::Library;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tree_sitter::ffi::TSLanguage;
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+
+use crate::native::build_shared_library;
+use crate::native::NativeToolchain;
+use crate::Language;
+
+/// Returns the directory the grammars loaded at runtime are built into, next to the grammars
+/// directory.
+pub fn parsers_directory(directory: &Path) -> PathBuf {
+    directory.join("..").join("native").join("parsers")
+}
+
+/// Loads the grammar named `symbol` in `grammar_directory`, one of the grammars of the repository
+/// of `language`, into the runtime. Its shared library is built first unless one newer than its
+/// sources already exists.
+pub async fn load_grammar(
+    toolchain: &NativeToolchain,
+    language: &Language,
+    grammar_directory: &Path,
+    symbol: &str,
+    parsers_directory: &Path,
+) -> Result<tree_sitter::Language, String> {
+    let library = parsers_directory.join(format!("{}.{}", symbol, std::env::consts::DLL_EXTENSION));
+    let built = library
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let changed = newest_modification(&grammar_directory.join("src"));
+    if built.is_none() || changed > built {
+        fs::create_dir_all(parsers_directory)
+            .map_err(|e| format!("Failed to create {}: {}", parsers_directory.display(), e))?;
+        build_shared_library(toolchain, language, grammar_directory, &library).await?;
+    }
+    load_language(&library, symbol)
+}
+
+/// Loads the grammar named `symbol` from a shared library built by `build_shared_library`.
+pub fn load_language(library: &Path, symbol: &str) -> Result<tree_sitter::Language, String> {
+    let entry_point = format!("tree_sitter_{}", symbol);
+    // SAFETY: the library is a grammar we compiled, whose entry point has this signature
+    unsafe {
+        let loaded = Library::new(library)
+            .map_err(|e| format!("Failed to load {}: {}", library.display(), e))?;
+        let language_fn = *loaded
+            .get::<unsafe extern "C" fn() -> *const TSLanguage>(entry_point.as_bytes())
+            .map_err(|e| format!("No {} in {}: {}", entry_point, library.display(), e))?;
+        let language = tree_sitter::Language::from_raw(language_fn());
+        // the language points into the library, so it has to stay loaded
+        std::mem::forget(loaded);
+        Ok(language)
+    }
+}
+
+/// Parses a source file with a grammar loaded into the runtime.
+pub fn parse(grammar: &tree_sitter::Language, source: &[u8]) -> Result<Tree, String> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(grammar)
+        .map_err(|e| format!("Failed to load grammar: {}", e))?;
+    parser
+        .parse(source, None)
+        .ok_or_else(|| "Parsing was cancelled".to_string())
+}
+
+/// Returns when a file below `directory` was last modified.
+fn newest_modification(directory: &Path) -> Option<SystemTime> {
+    let mut newest = None;
+    for entry in fs::read_dir(directory).ok()?.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let modified = match path.is_dir() {
+            true => newest_modification(&path),
+            false => entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        };
+        newest = newest.max(modified);
+    }
+    newest
+}
@@ -0,0 +1,758 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::metadata::flavored_query_paths;
+use crate::metadata::grammar_units;
+use crate::metadata::language_grammar_unit;
+use crate::metadata::read_grammar_metadata;
+use crate::metadata::read_query;
+use crate::metadata::root_grammar_metadata;
+use crate::metadata::select_query_flavor;
+use crate::metadata::GrammarUnit;
+use crate::Language;
+
+/// Directories of a grammar repository copied into generated crates besides the `src` directory
+/// of each grammar, which hold everything shared their parsers and scanners include.
+const SHARED_SOURCE_DIRECTORIES: [&str; 2] = ["common", "include"];
+
+/// Queries exposed as constants by generated crates, with the name of their constant.
+const QUERY_CONSTANTS: [(&str, &str); 4] = [
+    ("highlights", "HIGHLIGHTS_QUERY"),
+    ("injections", "INJECTIONS_QUERY"),
+    ("locals", "LOCALS_QUERY"),
+    ("tags", "TAGS_QUERY"),
+];
+
+/// Function of generated build scripts compiling a grammar's parser and scanner, with C++
+/// scanners compiled into a library of their own.
+const COMPILE_FUNCTION: &str = r#"fn compile(src: &Path, scanner: Option<&str>, library: &str) {
+    let mut build = cc::Build::new();
+    build
+        .include(src)
+        .warnings(false)
+        .flag_if_supported("-Wno-unused-parameter")
+        .flag_if_supported("-Wno-unused-but-set-variable")
+        .flag_if_supported("-Wno-trigraphs")
+        .file(src.join("parser.c"));
+    if let Some(scanner) = scanner.filter(|scanner| scanner.ends_with(".c")) {
+        build.file(src.join(scanner));
+    }
+    build.compile(library);
+
+    if let Some(scanner) = scanner.filter(|scanner| scanner.ends_with(".cc")) {
+        cc::Build::new()
+            .cpp(true)
+            .include(src)
+            .warnings(false)
+            .file(src.join(scanner))
+            .compile(&format!("{}-scanner", library));
+    }
+    println!("cargo:rerun-if-changed={}", src.display());
+}
+"#;
+
+/// Version of the `tree-sitter` crate bindings are written against by default, the one this
+/// program itself is built with.
+pub const DEFAULT_TREE_SITTER_VERSION: &str = "0.25";
+
+/// Version of the `tree-sitter-language` crate used by bindings for `tree-sitter` 0.23 and later.
+const TREE_SITTER_LANGUAGE_VERSION: &str = "0.1";
+
+/// A `tree-sitter` version requirement that generated bindings are written against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeSitterVersion {
+    pub requirement: String,
+    pub major: u64,
+    pub minor: u64,
+}
+
+impl std::str::FromStr for TreeSitterVersion {
+    type Err = String;
+
+    /// Parses a version requirement such as `0.25`, `0.22.6` or `~0.22.2`.
+    fn from_str(requirement: &str) -> Result<Self, Self::Err> {
+        let version = requirement.trim_start_matches(['~', '^', '=', ' ']);
+        let mut parts = version.split('.').map(|part| part.parse::<u64>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) if parts.all(|part| part.is_ok()) => Ok(Self {
+                requirement: requirement.to_string(),
+                major,
+                minor,
+            }),
+            _ => Err(format!(
+                "invalid tree-sitter version '{}', expected e.g. '0.25'",
+                requirement
+            )),
+        }
+    }
+}
+
+impl TreeSitterVersion {
+    /// Whether grammars expose a `LanguageFn` from `tree-sitter-language` instead of returning a
+    /// `tree_sitter::Language` directly, which changed in `tree-sitter` 0.23.
+    pub fn uses_language_fn(&self) -> bool {
+        self.major > 0 || self.minor >= 23
+    }
+}
+
+/// Returns the Rust identifier of a language, e.g. `c_sharp` for `c-sharp`.
+pub fn module_name(language_name: &str) -> String {
+    language_name.replace(['-', '.'], "_")
+}
+
+/// Returns the Rust binding of a grammar's entry point for a `tree-sitter` version: a
+/// `language<suffix>()` function, plus a `LANGUAGE<SUFFIX>` constant for versions using
+/// `tree-sitter-language`. The suffix tells apart the grammars of repositories with several.
+pub fn language_binding(
+    symbol: &str,
+    version: &TreeSitterVersion,
+    indent: &str,
+    suffix: &str,
+) -> String {
+    let constant_suffix = suffix.to_uppercase();
+    if version.uses_language_fn() {
+        format!(
+            "{indent}extern \"C\" {{\n\
+             {indent}    fn {symbol}() -> *const ();\n\
+             {indent}}}\n\n\
+             {indent}/// The tree-sitter [`LanguageFn`](tree_sitter_language::LanguageFn) of this grammar.\n\
+             {indent}pub const LANGUAGE{constant_suffix}: tree_sitter_language::LanguageFn =\n\
+             {indent}    unsafe {{ tree_sitter_language::LanguageFn::from_raw({symbol}) }};\n\n\
+             {indent}/// Returns the tree-sitter [`Language`](tree_sitter::Language) of this grammar.\n\
+             {indent}pub fn language{suffix}() -> tree_sitter::Language {{\n\
+             {indent}    LANGUAGE{constant_suffix}.into()\n\
+             {indent}}}\n"
+        )
+    } else {
+        format!(
+            "{indent}extern \"C\" {{\n\
+             {indent}    fn {symbol}() -> tree_sitter::Language;\n\
+             {indent}}}\n\n\
+             {indent}/// Returns the tree-sitter [`Language`](tree_sitter::Language) of this grammar.\n\
+             {indent}pub fn language{suffix}() -> tree_sitter::Language {{\n\
+             {indent}    unsafe {{ {symbol}() }}\n\
+             {indent}}}\n"
+        )
+    }
+}
+
+/// Generates a crate at `output` exposing every grammar with a generated `src/parser.c` behind a
+/// cargo feature named after its language, or after the grammar itself for repositories with
+/// several, like `typescript` and `tsx`, all built against a single `tree-sitter` version.
+/// Grammar sources and queries are copied into the crate, so it does not depend on the grammars
+/// directory. Queries use the variants of `query_flavor`, or the flavor configured for the
+/// language, where the grammar has them. Returns the features included.
+pub fn generate_rust_crate(
+    languages: &BTreeMap<String, Language>,
+    directory: &Path,
+    output: &Path,
+    crate_name: &str,
+    version: &TreeSitterVersion,
+    query_flavor: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let mut grammars: Vec<CrateGrammar> = Vec::new();
+    for (language_name, language) in languages {
+        let grammar_directory = directory.join(&language.name);
+        let units: Vec<GrammarUnit> = grammar_units(language, &grammar_directory)
+            .into_iter()
+            .filter(|unit| {
+                grammar_directory
+                    .join(unit.src())
+                    .join("parser.c")
+                    .is_file()
+            })
+            .collect();
+        if units.is_empty() {
+            println!("Skipping {}, it has no src/parser.c", language_name);
+            continue;
+        }
+        let main_symbol = language_grammar_unit(language_name, language, &grammar_directory)
+            .map(|unit| unit.symbol);
+        let several = units.len() > 1;
+        for unit in units {
+            let feature = match several {
+                true => unit.symbol.clone(),
+                false => language_name.clone(),
+            };
+            // the language's own name and aliases look up the grammar parsing the language itself
+            let mut names = vec![feature.clone()];
+            if !several || main_symbol.as_ref() == Some(&unit.symbol) {
+                names.push(language_name.clone());
+                names.extend(language.aliases.iter().cloned());
+                names.dedup();
+            }
+            if let Some(other) = grammars.iter().find(|grammar| grammar.feature == feature) {
+                return Err(format!(
+                    "Languages {} and {} both define the feature {}",
+                    other.language_name, language_name, feature
+                ));
+            }
+            grammars.push(CrateGrammar {
+                feature,
+                names,
+                language_name,
+                language,
+                unit,
+                several,
+            });
+        }
+    }
+
+    for generated in ["grammars", "queries", "src"] {
+        let path = output.join(generated);
+        if path.exists() {
+            fs::remove_dir_all(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+    }
+
+    let mut features = String::new();
+    let mut build_steps = String::new();
+    let mut modules = String::new();
+    let mut names = String::new();
+    let mut arms = String::new();
+
+    for grammar in &grammars {
+        let CrateGrammar {
+            feature,
+            language_name,
+            language,
+            unit,
+            ..
+        } = grammar;
+        let grammar_directory = directory.join(&language.name);
+        let module = module_name(feature);
+        let symbol = format!("tree_sitter_{}", unit.symbol);
+        let src = format!("grammars/{}/{}", language_name, unit.src());
+
+        for source_directory in std::iter::once(unit.src()).chain(
+            SHARED_SOURCE_DIRECTORIES
+                .iter()
+                .map(|directory| directory.to_string()),
+        ) {
+            let source = grammar_directory.join(&source_directory);
+            let destination = output
+                .join("grammars")
+                .join(language_name)
+                .join(&source_directory);
+            if source.is_dir() && !destination.exists() {
+                copy_directory(&source, &destination)
+                    .map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+            }
+        }
+
+        let _ = writeln!(features, "{} = []", feature);
+
+        let scanner = ["scanner.c", "scanner.cc"]
+            .into_iter()
+            .find(|scanner| grammar_directory.join(unit.src()).join(scanner).is_file());
+        let library = match grammar.several {
+            true => format!("tree-sitter-{}", unit.symbol),
+            false => language.name.clone(),
+        };
+        let _ = writeln!(
+            build_steps,
+            "    if env::var_os(\"CARGO_FEATURE_{}\").is_some() {{\n        {}\n    }}",
+            module.to_uppercase(),
+            compile_call(&src, scanner, &library),
+        );
+
+        let _ = writeln!(modules, "#[cfg(feature = \"{}\")]", feature);
+        let _ = writeln!(modules, "pub mod {} {{", module);
+        modules.push_str(&language_binding(&symbol, version, "    ", ""));
+        if grammar_directory
+            .join(unit.src())
+            .join("node-types.json")
+            .is_file()
+        {
+            let _ = write!(
+                modules,
+                "\n    pub const NODE_TYPES: &str =\n        include_str!(\"../{}/node-types.json\");\n",
+                src
+            );
+        }
+        let metadata = match grammar.several {
+            true => read_grammar_metadata(&grammar_directory)
+                .into_iter()
+                .find(|metadata| metadata.path.as_deref().unwrap_or_default() == unit.path),
+            false => root_grammar_metadata(&grammar_directory),
+        }
+        .unwrap_or_default();
+        let flavor = select_query_flavor(language, &grammar_directory, query_flavor);
+        for (kind, constant) in QUERY_CONSTANTS {
+            // grammars of a repository with several may have queries of their own
+            let own_queries = grammar_directory.join(&unit.path);
+            let queries_directory = match metadata.queries(kind).is_empty()
+                && own_queries.join(format!("queries/{}.scm", kind)).is_file()
+            {
+                true => own_queries,
+                false => grammar_directory.clone(),
+            };
+            let Some(query) = read_query(&queries_directory, &metadata, kind, flavor.as_deref())
+            else {
+                continue;
+            };
+            let path = output
+                .join("queries")
+                .join(feature)
+                .join(format!("{}.scm", kind));
+            write_file(&path, &query)?;
+            let _ = write!(
+                modules,
+                "\n    pub const {}: &str = include_str!(\"../queries/{}/{}.scm\");\n",
+                constant, feature, kind
+            );
+        }
+        modules.push_str("}\n\n");
+
+        let _ = writeln!(names, "    #[cfg(feature = \"{}\")]", feature);
+        let _ = writeln!(names, "    \"{}\",", feature);
+
+        let patterns: Vec<String> = grammar
+            .names
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect();
+        let _ = writeln!(arms, "        #[cfg(feature = \"{}\")]", feature);
+        let _ = writeln!(
+            arms,
+            "        {} => Some({}::language()),",
+            patterns.join(" | "),
+            module
+        );
+    }
+
+    let all: Vec<String> = grammars
+        .iter()
+        .map(|grammar| format!("\"{}\"", grammar.feature))
+        .collect();
+    let language_fn_dependency = if version.uses_language_fn() {
+        format!(
+            "tree-sitter-language = \"{}\"\n",
+            TREE_SITTER_LANGUAGE_VERSION
+        )
+    } else {
+        String::new()
+    };
+    let cargo_toml = format!(
+        "# Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars rust-crate`.\n\n\
+         [package]\n\
+         name = \"{crate_name}\"\n\
+         version = \"0.1.0\"\n\
+         edition = \"2021\"\n\
+         description = \"Vendored tree-sitter grammars, one cargo feature per grammar\"\n\
+         build = \"build.rs\"\n\n\
+         [workspace]\n\n\
+         [features]\n\
+         default = []\n\
+         all = [{all}]\n\
+         {features}\n\
+         [dependencies]\n\
+         tree-sitter = \"{tree_sitter}\"\n\
+         {language_fn_dependency}\n\
+         [build-dependencies]\n\
+         cc = \"1.0\"\n",
+        all = all.join(", "),
+        tree_sitter = version.requirement,
+    );
+    write_file(&output.join("Cargo.toml"), &cargo_toml)?;
+
+    let build_rs = format!(
+        "// Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars rust-crate`.\n\n\
+         use std::env;\n\
+         use std::path::Path;\n\n\
+         fn main() {{\n\
+         {build_steps}}}\n\n\
+         {COMPILE_FUNCTION}"
+    );
+    write_file(&output.join("build.rs"), &build_rs)?;
+
+    let lib_rs = format!(
+        "// Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars rust-crate`.\n\n\
+         //! Vendored tree-sitter grammars, each enabled by the cargo feature of the same name.\n\n\
+         {modules}\
+         /// Names of the languages enabled in this build.\n\
+         pub const LANGUAGES: &[&str] = &[\n\
+         {names}];\n\n\
+         /// Returns the language with the given name or alias, if its feature is enabled.\n\
+         pub fn language(name: &str) -> Option<tree_sitter::Language> {{\n    \
+             match name {{\n\
+         {arms}        \
+                 _ => None,\n    \
+             }}\n\
+         }}\n"
+    );
+    write_file(&output.join("src/lib.rs"), &lib_rs)?;
+
+    Ok(grammars
+        .into_iter()
+        .map(|grammar| grammar.feature)
+        .collect())
+}
+
+/// A grammar exposed by a generated crate behind its own feature.
+struct CrateGrammar<'a> {
+    feature: String,
+    /// Names `language()` looks the grammar up by
+    names: Vec<String>,
+    language_name: &'a String,
+    language: &'a Language,
+    unit: GrammarUnit,
+    /// Whether the grammar is one of several in its repository
+    several: bool,
+}
+
+/// Returns the call of `COMPILE_FUNCTION` compiling the grammar in `src`.
+fn compile_call(src: &str, scanner: Option<&str>, library: &str) -> String {
+    format!(
+        "compile(Path::new(\"{}\"), {}, \"{}\");",
+        src,
+        scanner.map_or("None".to_string(), |scanner| format!(
+            "Some(\"{}\")",
+            scanner
+        )),
+        library
+    )
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn copy_directory(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            copy_directory(&path, &destination.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, destination.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Fields of a grammar's `[package]` table kept when its bindings are regenerated.
+const PACKAGE_METADATA: [&str; 9] = [
+    "description",
+    "version",
+    "authors",
+    "license",
+    "readme",
+    "homepage",
+    "repository",
+    "keywords",
+    "categories",
+];
+
+/// Whether every grammar of a repository has a generated parser, so its bindings compile.
+pub fn has_generated_parsers(language: &Language, grammar_directory: &Path) -> bool {
+    let units = grammar_units(language, grammar_directory);
+    !units.is_empty()
+        && units.iter().all(|unit| {
+            grammar_directory
+                .join(unit.src())
+                .join("parser.c")
+                .is_file()
+        })
+}
+
+/// Replaces the `Cargo.toml`, `bindings/rust/build.rs` and `bindings/rust/lib.rs` of a grammar
+/// with ones written against a single `tree-sitter` version, keeping the package metadata of an
+/// existing `Cargo.toml` or taking it from `package.json`. Query constants use the variants of
+/// `query_flavor`, or the flavor configured for the language, where the grammar has them.
+pub fn write_grammar_bindings(
+    language: &Language,
+    grammar_directory: &Path,
+    version: &TreeSitterVersion,
+    query_flavor: Option<&str>,
+) -> Result<(), String> {
+    let units = grammar_units(language, grammar_directory);
+    if units.is_empty() {
+        return Err(format!(
+            "No grammar found in {}",
+            grammar_directory.display()
+        ));
+    }
+    let flavor = select_query_flavor(language, grammar_directory, query_flavor);
+    let suffix = |unit: &GrammarUnit| match units.len() {
+        1 => String::new(),
+        _ => format!("_{}", unit.symbol),
+    };
+
+    let mut include = vec!["bindings/rust/*".to_string(), "queries/*".to_string()];
+    include.extend(units.iter().map(|unit| format!("{}/*", unit.src())));
+    for shared in ["common", "include"] {
+        if grammar_directory.join(shared).is_dir() {
+            include.push(format!("{}/*", shared));
+        }
+    }
+    let include: Vec<String> = include
+        .iter()
+        .map(|pattern| toml::Value::String(pattern.clone()).to_string())
+        .collect();
+
+    let language_fn_dependency = if version.uses_language_fn() {
+        format!(
+            "tree-sitter-language = \"{}\"\n",
+            TREE_SITTER_LANGUAGE_VERSION
+        )
+    } else {
+        String::new()
+    };
+    let cargo_toml = format!(
+        "# Generated by `tree-sitter-grammars bindings`.\n\n\
+         [package]\n\
+         name = {name}\n\
+         {metadata}\
+         edition = \"2021\"\n\
+         autoexamples = false\n\n\
+         build = \"bindings/rust/build.rs\"\n\
+         include = [{include}]\n\n\
+         [lib]\n\
+         path = \"bindings/rust/lib.rs\"\n\n\
+         [dependencies]\n\
+         tree-sitter = \"{tree_sitter}\"\n\
+         {language_fn_dependency}\n\
+         [build-dependencies]\n\
+         cc = \"1.0\"\n",
+        name = toml::Value::String(language.name.clone()),
+        metadata = package_metadata(language, grammar_directory),
+        include = include.join(", "),
+        tree_sitter = version.requirement,
+    );
+    write_file(&grammar_directory.join("Cargo.toml"), &cargo_toml)?;
+
+    let compile_calls: String = units
+        .iter()
+        .map(|unit| {
+            let scanner = ["scanner.c", "scanner.cc"]
+                .into_iter()
+                .find(|scanner| grammar_directory.join(unit.src()).join(scanner).is_file());
+            let library = format!("tree-sitter-{}", unit.symbol);
+            format!("    {}\n", compile_call(&unit.src(), scanner, &library))
+        })
+        .collect();
+    let build_rs = format!(
+        "// Generated by `tree-sitter-grammars bindings`.\n\n\
+         use std::path::Path;\n\n\
+         fn main() {{\n\
+         {compile_calls}}}\n\n\
+         {COMPILE_FUNCTION}"
+    );
+    write_file(&grammar_directory.join("bindings/rust/build.rs"), &build_rs)?;
+
+    let metadata = read_grammar_metadata(grammar_directory);
+    let mut lib_rs = format!(
+        "// Generated by `tree-sitter-grammars bindings`.\n\n\
+         //! This crate provides the {} grammar for the [tree-sitter](https://tree-sitter.github.io/) parsing library.\n",
+        language.name.trim_start_matches("tree-sitter-")
+    );
+    for unit in &units {
+        let suffix = suffix(unit);
+        lib_rs.push('\n');
+        lib_rs.push_str(&language_binding(
+            &format!("tree_sitter_{}", unit.symbol),
+            version,
+            "",
+            &suffix,
+        ));
+        if grammar_directory
+            .join(unit.src())
+            .join("node-types.json")
+            .is_file()
+        {
+            let _ = write!(
+                lib_rs,
+                "\n/// The content of the `node-types.json` file for this grammar.\n\
+                 pub const NODE_TYPES{}: &str = include_str!(\"../../{}/node-types.json\");\n",
+                suffix.to_uppercase(),
+                unit.src()
+            );
+        }
+
+        let grammar = metadata
+            .iter()
+            .find(|grammar| grammar.path.as_deref().unwrap_or_default() == unit.path)
+            .cloned()
+            .unwrap_or_default();
+        for (kind, constant) in QUERY_CONSTANTS {
+            let queries = crate_queries(
+                grammar_directory,
+                &grammar,
+                &unit.path,
+                kind,
+                flavor.as_deref(),
+            );
+            let includes: Vec<String> = queries
+                .iter()
+                .map(|query| format!("include_str!(\"../../{}\")", query))
+                .collect();
+            let value = match includes.as_slice() {
+                [] => continue,
+                [include] => include.clone(),
+                includes => format!("concat!({})", includes.join(", \"\\n\", ")),
+            };
+            let _ = write!(
+                lib_rs,
+                "\npub const {}{}: &str = {};\n",
+                constant,
+                suffix.to_uppercase(),
+                value
+            );
+        }
+    }
+
+    // `Parser::set_language` takes the language by reference since tree-sitter 0.22
+    let reference = if version.major > 0 || version.minor >= 22 {
+        "&"
+    } else {
+        ""
+    };
+    let tests: String = units
+        .iter()
+        .map(|unit| {
+            let suffix = suffix(unit);
+            format!(
+                "\n    #[test]\n    \
+                 fn test_can_load_grammar{suffix}() {{\n        \
+                     let mut parser = tree_sitter::Parser::new();\n        \
+                     parser\n            \
+                         .set_language({reference}super::language{suffix}())\n            \
+                         .expect(\"Error loading grammar\");\n    \
+                 }}\n"
+            )
+        })
+        .collect();
+    let _ = write!(lib_rs, "\n#[cfg(test)]\nmod tests {{{}}}\n", tests);
+    write_file(&grammar_directory.join("bindings/rust/lib.rs"), &lib_rs)
+}
+
+/// Returns the `[package]` fields of an existing `Cargo.toml`, or those that can be taken from
+/// `package.json` and the languages file for grammars without one.
+fn package_metadata(language: &Language, grammar_directory: &Path) -> String {
+    let existing = fs::read_to_string(grammar_directory.join("Cargo.toml"))
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .and_then(|manifest| manifest.get("package")?.as_table().cloned());
+
+    let package = existing.unwrap_or_else(|| {
+        let package_json: serde_json::Value =
+            fs::read_to_string(grammar_directory.join("package.json"))
+                .ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok())
+                .unwrap_or_default();
+        let mut package = toml::Table::new();
+        for field in ["description", "version", "license"] {
+            if let Some(value) = package_json[field].as_str() {
+                package.insert(field.to_string(), toml::Value::String(value.to_string()));
+            }
+        }
+        package
+            .entry("version")
+            .or_insert_with(|| toml::Value::String("0.0.1".to_string()));
+        package.insert(
+            "repository".to_string(),
+            toml::Value::String(language.git.trim_end_matches(".git").to_string()),
+        );
+        package
+    });
+
+    PACKAGE_METADATA
+        .iter()
+        .filter_map(|field| {
+            let value = package.get(*field)?;
+            // a README moved by an upstream change would break packaging
+            if *field == "readme"
+                && value
+                    .as_str()
+                    .is_some_and(|readme| !grammar_directory.join(readme).is_file())
+            {
+                return None;
+            }
+            Some(format!("{} = {}\n", field, value))
+        })
+        .collect()
+}
+
+/// Returns the query files of a kind for a grammar of a repository, relative to the repository:
+/// the ones listed in its metadata that are part of the repository, or otherwise the grammar's own
+/// or the repository's `queries/<kind>.scm`, with `flavor` applied as described in
+/// `flavored_query_paths`.
+fn crate_queries(
+    grammar_directory: &Path,
+    grammar: &crate::metadata::GrammarMetadata,
+    unit_path: &str,
+    kind: &str,
+    flavor: Option<&str>,
+) -> Vec<String> {
+    let mut queries: Vec<String> = grammar
+        .queries(kind)
+        .iter()
+        .filter(|query| !query.starts_with("node_modules/"))
+        .filter(|query| grammar_directory.join(query).is_file())
+        .cloned()
+        .collect();
+    if queries.is_empty() {
+        queries = [
+            format!("{}/queries/{}.scm", unit_path, kind),
+            format!("queries/{}.scm", kind),
+        ]
+        .into_iter()
+        .map(|query| query.trim_start_matches('/').to_string())
+        .find(|query| grammar_directory.join(query).is_file())
+        .into_iter()
+        .collect();
+    }
+
+    let paths = queries
+        .iter()
+        .map(|query| grammar_directory.join(query))
+        .collect();
+    flavored_query_paths(grammar_directory, paths, kind, flavor)
+        .iter()
+        .filter_map(|path| path.strip_prefix(grammar_directory).ok())
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect()
+}
+
+/// Generates the `Cargo.toml` of a workspace in the grammars directory with every grammar whose
+/// bindings compile as a member, excluding grammars with bindings but without generated parsers.
+pub fn grammars_workspace(
+    languages: &BTreeMap<String, Language>,
+    directory: &Path,
+) -> (String, usize) {
+    let mut members = Vec::new();
+    let mut exclude = Vec::new();
+    for language in languages.values() {
+        let grammar_directory = directory.join(&language.name);
+        if !grammar_directory.join("Cargo.toml").is_file() {
+            continue;
+        }
+        let entry = format!("    \"{}\",\n", language.name);
+        if has_generated_parsers(language, &grammar_directory) {
+            members.push(entry);
+        } else {
+            exclude.push(entry);
+        }
+    }
+
+    let workspace = format!(
+        "# Automatically generated, DO NOT EDIT! Updated by `tree-sitter-grammars bindings`.\n\n\
+         [workspace]\n\
+         resolver = \"2\"\n\
+         members = [\n{}]\n\
+         exclude = [\n{}]\n",
+        members.concat(),
+        exclude.concat()
+    );
+    (workspace, members.len())
+}
@@ -0,0 +1,141 @@
+use serde_json::json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::hashing::file_sha256;
+use crate::hashing::tree_sha256;
+use crate::licenses::detect_license;
+use crate::licenses::find_license_files;
+use crate::lockfile::grammar_commit;
+use crate::lockfile::Lockfile;
+use crate::validate::artifact_file_names;
+use crate::Language;
+
+/// Creates a CycloneDX 1.5 document listing every grammar in the languages file and every
+/// WebAssembly artifact built from them. Commits are taken from the lockfile, falling back to the
+/// pinned hash, and content hashes are computed from the vendored files, so nothing is fetched.
+pub fn cyclonedx_sbom(
+    languages: &BTreeMap<String, Language>,
+    lockfile: &Lockfile,
+    directory: &Path,
+    wasm_directory: &Path,
+) -> Value {
+    let mut components = Vec::new();
+    let mut dependencies = Vec::new();
+
+    for (language_name, language) in languages {
+        let grammar_directory = directory.join(&language.name);
+        let commit = grammar_commit(lockfile, language_name, language);
+
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": grammar_ref(language_name),
+            "name": language.name,
+            "purl": purl(language, commit.as_deref()),
+            "externalReferences": [{ "type": "vcs", "url": language.git }],
+        });
+        if let Some(commit) = &commit {
+            component["version"] = json!(commit);
+        }
+        if let Ok(digest) = tree_sha256(&grammar_directory) {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": digest }]);
+        }
+        let license_files = find_license_files(&grammar_directory);
+        match detect_license(&grammar_directory, &license_files) {
+            // a choice between licenses is only expressible as an SPDX expression
+            Some(spdx) if spdx.contains(" OR ") => {
+                component["licenses"] = json!([{ "expression": spdx }]);
+            }
+            Some(spdx) => component["licenses"] = json!([{ "license": { "id": spdx } }]),
+            None => {}
+        }
+        components.push(component);
+
+        if !language.dependencies.is_empty() {
+            let depends_on: Vec<String> = language
+                .dependencies
+                .iter()
+                .map(|dependency| grammar_ref(dependency))
+                .collect();
+            dependencies
+                .push(json!({ "ref": grammar_ref(language_name), "dependsOn": depends_on }));
+        }
+    }
+
+    let mut artifacts: Vec<_> = fs::read_dir(wasm_directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "wasm")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    artifacts.sort();
+
+    for artifact in artifacts {
+        let file_name = artifact
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let artifact_ref = format!("wasm:{}", file_name);
+
+        let mut component = json!({
+            "type": "file",
+            "bom-ref": artifact_ref,
+            "name": file_name,
+        });
+        if let Ok(digest) = file_sha256(&artifact) {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": digest }]);
+        }
+        components.push(component);
+
+        if let Some((language_name, _)) = languages.iter().find(|(language_name, language)| {
+            artifact_file_names(language_name, language).contains(&file_name)
+        }) {
+            dependencies.push(json!({
+                "ref": artifact_ref,
+                "dependsOn": [grammar_ref(language_name)],
+            }));
+        }
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn grammar_ref(language_name: &str) -> String {
+    format!("grammar:{}", language_name)
+}
+
+/// Returns a package URL for a grammar, using the `github` type for repositories hosted there.
+fn purl(language: &Language, commit: Option<&str>) -> String {
+    let version = commit.map_or(String::new(), |commit| format!("@{}", commit));
+    let path = language
+        .git
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .replace("git@github.com:", "https://github.com/");
+    match path.split_once("github.com/") {
+        Some((_, repository)) => format!("pkg:github/{}{}", repository.to_lowercase(), version),
+        None => format!("pkg:generic/{}{}", language.name, version),
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::corpus::format_sexp;
+use crate::corpus::tree_diff;
+use crate::runtime::parse;
+use crate::Language;
+
+/// Extension of the files snapshots are stored in, appended to the name of their sample.
+const SNAPSHOT_EXTENSION: &str = "sexp";
+
+/// The number of error nodes in a tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    /// `ERROR` nodes, for input the grammar could not parse
+    pub errors: usize,
+    /// `MISSING` nodes, for tokens the parser had to insert
+    pub missing: usize,
+}
+
+impl fmt::Display for ErrorCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ERROR, {} MISSING", self.errors, self.missing)
+    }
+}
+
+/// Counts the error nodes of a tree given as an S-expression.
+pub fn error_counts(sexp: &str) -> ErrorCounts {
+    ErrorCounts {
+        errors: sexp.matches("(ERROR").count(),
+        missing: sexp.matches("(MISSING").count(),
+    }
+}
+
+/// How the tree of a sample file compares to its stored snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotStatus {
+    /// No snapshot of the file was stored yet
+    New,
+    Unchanged,
+    /// The tree changed without gaining error nodes
+    Changed,
+    /// The tree has more `ERROR` or `MISSING` nodes than before
+    NewErrors {
+        before: ErrorCounts,
+        after: ErrorCounts,
+    },
+    /// A snapshot is stored for a file that is no longer a sample
+    Removed,
+}
+
+/// The snapshot of a sample file, relative to the samples directory.
+#[derive(Debug, Clone)]
+pub struct SampleSnapshot {
+    pub path: String,
+    pub status: SnapshotStatus,
+    /// Diff of the stored and current tree, for changed trees
+    pub diff: Option<String>,
+}
+
+/// Returns the directory of sample files of a language: the `samples` of the language in the
+/// languages file, otherwise `samples/<language>/` next to the languages file if it exists.
+pub fn language_samples(
+    language_name: &str,
+    language: &Language,
+    file_path: &Path,
+) -> Option<PathBuf> {
+    let base_directory = file_path.parent().unwrap_or(Path::new("."));
+    match &language.samples {
+        Some(samples) => Some(base_directory.join(samples)),
+        None => {
+            Some(base_directory.join("samples").join(language_name)).filter(|path| path.is_dir())
+        }
+    }
+}
+
+/// Parses every file in `samples` with a grammar and compares its tree with the snapshot stored
+/// in `snapshots`, in file name order. Snapshots of new files are stored right away, while
+/// changed and removed ones are only replaced with `update`.
+pub fn snapshot_samples(
+    grammar: &tree_sitter::Language,
+    samples: &Path,
+    snapshots: &Path,
+    update: bool,
+) -> Result<Vec<SampleSnapshot>, String> {
+    let mut results = Vec::new();
+    let samples_found = sample_files(samples)?;
+    for path in &samples_found {
+        let source = fs::read(samples.join(path))
+            .map_err(|e| format!("Failed to read {}: {}", samples.join(path).display(), e))?;
+        let tree = parse(grammar, &source)?;
+        let sexp = format_sexp(&tree.root_node().to_sexp()) + "\n";
+
+        let snapshot = snapshots.join(format!("{}.{}", path, SNAPSHOT_EXTENSION));
+        let stored = fs::read_to_string(&snapshot).ok();
+        let status = snapshot_status(stored.as_deref(), &sexp);
+        let diff = stored
+            .filter(|_| !matches!(status, SnapshotStatus::New | SnapshotStatus::Unchanged))
+            .map(|stored| tree_diff(&stored, &sexp));
+        if status == SnapshotStatus::New || (update && status != SnapshotStatus::Unchanged) {
+            if let Some(parent) = snapshot.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            fs::write(&snapshot, &sexp)
+                .map_err(|e| format!("Failed to write {}: {}", snapshot.display(), e))?;
+        }
+        results.push(SampleSnapshot {
+            path: path.clone(),
+            status,
+            diff,
+        });
+    }
+
+    let suffix = format!(".{}", SNAPSHOT_EXTENSION);
+    let stored = match snapshots.is_dir() {
+        true => sample_files(snapshots)?,
+        false => Vec::new(),
+    };
+    for snapshot in stored {
+        let Some(path) = snapshot.strip_suffix(&suffix) else {
+            continue;
+        };
+        if samples_found.iter().any(|sample| sample == path) {
+            continue;
+        }
+        if update {
+            let snapshot = snapshots.join(&snapshot);
+            fs::remove_file(&snapshot)
+                .map_err(|e| format!("Failed to remove {}: {}", snapshot.display(), e))?;
+        }
+        results.push(SampleSnapshot {
+            path: path.to_string(),
+            status: SnapshotStatus::Removed,
+            diff: None,
+        });
+    }
+    Ok(results)
+}
+
+/// Classifies the current tree of a sample against its stored snapshot, if there is one.
+fn snapshot_status(stored: Option<&str>, sexp: &str) -> SnapshotStatus {
+    let Some(stored) = stored else {
+        return SnapshotStatus::New;
+    };
+    if stored == sexp {
+        return SnapshotStatus::Unchanged;
+    }
+    let before = error_counts(stored);
+    let after = error_counts(sexp);
+    match after.errors > before.errors || after.missing > before.missing {
+        true => SnapshotStatus::NewErrors { before, after },
+        false => SnapshotStatus::Changed,
+    }
+}
+
+/// Returns the files below a directory relative to it, in file name order, without hidden ones.
+pub fn sample_files(directory: &Path) -> Result<Vec<String>, String> {
+    files(directory, "")
+}
+
+fn files(directory: &Path, relative: &str) -> Result<Vec<String>, String> {
+    let mut found = Vec::new();
+    let path = directory.join(relative);
+    let entries =
+        fs::read_dir(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let relative = match relative {
+            "" => name,
+            relative => format!("{}/{}", relative, name),
+        };
+        if entry.path().is_dir() {
+            found.extend(files(directory, &relative)?);
+        } else {
+            found.push(relative);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_trees_against_their_snapshot() {
+        let tree = "(program (call (identifier)))\n";
+        assert_eq!(snapshot_status(None, tree), SnapshotStatus::New);
+        assert_eq!(snapshot_status(Some(tree), tree), SnapshotStatus::Unchanged);
+        assert_eq!(
+            snapshot_status(Some(tree), "(program (call (member (identifier))))\n"),
+            SnapshotStatus::Changed
+        );
+        assert_eq!(
+            snapshot_status(Some("(program (ERROR (identifier)))\n"), tree),
+            SnapshotStatus::Changed
+        );
+        assert_eq!(
+            snapshot_status(Some(tree), "(program (ERROR (identifier)))\n"),
+            SnapshotStatus::NewErrors {
+                before: ErrorCounts::default(),
+                after: ErrorCounts {
+                    errors: 1,
+                    missing: 0
+                },
+            }
+        );
+        // trading an ERROR for a MISSING node is still a new error
+        assert_eq!(
+            snapshot_status(
+                Some("(program (ERROR (identifier)))\n"),
+                "(program (call (MISSING \")\")))\n"
+            ),
+            SnapshotStatus::NewErrors {
+                before: ErrorCounts {
+                    errors: 1,
+                    missing: 0
+                },
+                after: ErrorCounts {
+                    errors: 0,
+                    missing: 1
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn lists_sample_files_without_hidden_ones() {
+        let root = std::env::temp_dir().join(format!("samples-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for path in ["b.rs", "a/z.rs", "a/.hidden.rs", ".git/config"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let files = sample_files(&root);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(files.unwrap(), ["a/z.rs", "b.rs"]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::build::find_executable;
+use crate::build::grammar_symbol_name;
+use crate::corpus::format_sexp;
+use crate::corpus::has_fields;
+use crate::corpus::read_corpus;
+use crate::corpus::strip_fields;
+use crate::corpus::tree_diff;
+use crate::metadata::grammar_units;
+use crate::metadata::GrammarUnit;
+use crate::runtime::parse;
+use crate::Language;
+
+/// The test suites of a grammar, relative to its directory.
+#[derive(Debug, Clone, Default)]
+pub struct TestSuites {
+    /// Parse trees expected for inputs, in `test/corpus/` or the older `corpus/`
+    pub corpus: Option<PathBuf>,
+    /// Highlight assertions checked against the grammar's highlights query
+    pub highlight: Option<PathBuf>,
+    /// Tag assertions checked against the grammar's tags query
+    pub tags: Option<PathBuf>,
+}
+
+impl TestSuites {
+    pub fn is_empty(&self) -> bool {
+        self.corpus.is_none() && self.highlight.is_none() && self.tags.is_none()
+    }
+}
+
+/// Finds the test suites of the grammar in `directory`, where the `tree-sitter` CLI looks for
+/// them.
+pub fn test_suites(directory: &Path) -> TestSuites {
+    let find = |candidates: &[&str]| {
+        candidates
+            .iter()
+            .map(PathBuf::from)
+            .find(|candidate| directory.join(candidate).is_dir())
+    };
+    TestSuites {
+        corpus: find(&["test/corpus", "corpus"]),
+        highlight: find(&["test/highlight"]),
+        tags: find(&["test/tags"]),
+    }
+}
+
+/// Returns the grammars of a repository that have test suites, with their suites. A grammar
+/// whose parser is not generated yet is only found at the repository root.
+pub fn grammar_test_suites(
+    language: &Language,
+    grammar_directory: &Path,
+) -> Vec<(GrammarUnit, TestSuites)> {
+    let mut units = grammar_units(language, grammar_directory);
+    if units.is_empty() && grammar_directory.join("grammar.js").is_file() {
+        units.push(GrammarUnit {
+            path: String::new(),
+            symbol: grammar_symbol_name(language, grammar_directory),
+        });
+    }
+    units
+        .into_iter()
+        .map(|unit| {
+            let suites = test_suites(&grammar_directory.join(&unit.path));
+            (unit, suites)
+        })
+        .filter(|(_, suites)| !suites.is_empty())
+        .collect()
+}
+
+/// Outcome of running the tests of one grammar.
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Whether the runner succeeded; it may also fail without a failing test, e.g. when the
+    /// parser does not compile
+    pub success: bool,
+    /// Everything the runner printed
+    pub output: String,
+}
+
+/// Runs `tree-sitter test` in the directory of a grammar, which compiles its parser and checks
+/// the corpus, highlight and tag suites it finds.
+pub async fn run_tree_sitter_test(directory: &Path) -> Result<TestReport, String> {
+    let program = find_executable("tree-sitter")
+        .ok_or("No tree-sitter CLI found, install it to run grammar tests")?;
+    let output = tokio::process::Command::new(program)
+        .current_dir(directory)
+        .arg("test")
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute tree-sitter test: {}", e))?;
+
+    let mut report = TestReport {
+        success: output.status.success(),
+        output: String::from_utf8_lossy(&output.stdout).to_string(),
+        ..TestReport::default()
+    };
+    report
+        .output
+        .push_str(&String::from_utf8_lossy(&output.stderr));
+    // the CLI marks every test with a check, a cross or a skip sign, which newer versions number
+    for line in report.output.lines() {
+        let marker = line.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ' ');
+        if marker.starts_with('✓') {
+            report.passed += 1;
+        } else if marker.starts_with('✗') {
+            report.failed += 1;
+        } else if marker.starts_with('⌀') {
+            report.skipped += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Runs the corpus tests in `corpus_directory` with the grammar named `symbol`, loaded into the
+/// runtime, the way `tree-sitter test` does: the tree of each input must match the expected one
+/// after normalizing whitespace, ignoring fields if the expected tree names none. The tree diff of
+/// every failed test is printed after the list of tests.
+pub fn run_corpus(
+    grammar: &tree_sitter::Language,
+    symbol: &str,
+    corpus_directory: &Path,
+) -> Result<TestReport, String> {
+    let mut report = TestReport::default();
+    let mut failures = Vec::new();
+    'files: for (file, tests) in read_corpus(corpus_directory)? {
+        let _ = writeln!(report.output, "  {}:", file);
+        for test in tests {
+            // concrete syntax trees are only printed by the CLI
+            if !test.applies_to(symbol) || test.attributes.cst {
+                report.skipped += 1;
+                let _ = writeln!(report.output, "    ⌀ {}", test.name);
+                continue;
+            }
+
+            let tree = parse(grammar, test.input.as_bytes())?;
+            let root = tree.root_node();
+            let failure = if test.attributes.error {
+                (!root.has_error())
+                    .then(|| "  expected the input to parse with errors\n".to_string())
+            } else {
+                let mut actual = root.to_sexp();
+                if !has_fields(&test.expected) {
+                    actual = strip_fields(&actual);
+                }
+                (actual != test.expected)
+                    .then(|| tree_diff(&format_sexp(&test.expected), &format_sexp(&actual)))
+            };
+
+            match failure {
+                None => {
+                    report.passed += 1;
+                    let _ = writeln!(report.output, "    ✓ {}", test.name);
+                }
+                Some(diff) => {
+                    report.failed += 1;
+                    let _ = writeln!(report.output, "    ✗ {}", test.name);
+                    failures.push((format!("{} ({}:{})", test.name, file, test.line), diff));
+                    if test.attributes.fail_fast {
+                        break 'files;
+                    }
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        let _ = writeln!(
+            report.output,
+            "\n{} failure{}, expected (-) and actual (+) trees:",
+            failures.len(),
+            if failures.len() == 1 { "" } else { "s" }
+        );
+        for (index, (name, diff)) in failures.iter().enumerate() {
+            let _ = write!(report.output, "\n  {}. {}:\n\n{}", index + 1, name, diff);
+        }
+    }
+    report.success = report.failed == 0;
+    Ok(report)
+}
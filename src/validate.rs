@@ -0,0 +1,219 @@
+use std::fs;
+use std::path::Path;
+use wasmparser::DataKind;
+use wasmparser::ExternalKind;
+use wasmparser::Operator;
+use wasmparser::Parser;
+use wasmparser::Payload;
+use wasmparser::TypeRef;
+use wasmparser::Validator;
+
+use crate::abi::AbiRange;
+use crate::metadata::GrammarUnit;
+use crate::Language;
+
+/// Symbols the runtime provides to grammar side modules: the dynamic linking globals, the subset
+/// of the C standard library exposed by tree-sitter, and the emscripten runtime functions pulled
+/// in by assertions in external scanners.
+const ALLOWED_IMPORTS: [&str; 37] = [
+    "memory",
+    "__memory_base",
+    "__table_base",
+    "__indirect_function_table",
+    "__stack_pointer",
+    "__assert_fail",
+    "abort",
+    "exit",
+    "fwrite",
+    "stderr",
+    "calloc",
+    "free",
+    "malloc",
+    "realloc",
+    "memchr",
+    "memcmp",
+    "memcpy",
+    "memmove",
+    "memset",
+    "strcmp",
+    "strlen",
+    "strncat",
+    "strncmp",
+    "strncpy",
+    "iswalnum",
+    "iswalpha",
+    "iswblank",
+    "iswdigit",
+    "iswlower",
+    "iswpunct",
+    "iswspace",
+    "iswupper",
+    "iswxdigit",
+    "isprint",
+    "towlower",
+    "towupper",
+    "putchar",
+];
+
+/// Modules grammar side modules may import from.
+const ALLOWED_IMPORT_MODULES: [&str; 3] = ["env", "GOT.mem", "GOT.func"];
+
+/// Returns the file names the WebAssembly artifact of a language may have.
+pub fn artifact_file_names(language_name: &str, language: &Language) -> Vec<String> {
+    // artifacts are named after either the language or its grammar repository
+    let mut stems = vec![language_name, language.name.as_str()];
+    stems.dedup();
+    stems
+        .into_iter()
+        .map(|stem| format!("{}.wasm", stem))
+        .collect()
+}
+
+/// Checks a WebAssembly artifact built from a grammar repository, which exports the
+/// `tree_sitter_<symbol>` function of each of its grammars. Returns the oldest ABI version of
+/// their languages.
+pub fn validate_wasm_grammars(
+    path: &Path,
+    units: &[GrammarUnit],
+    abi: &AbiRange,
+) -> Result<u32, String> {
+    if units.is_empty() {
+        return Err(format!("No grammars found for {}", path.display()));
+    }
+    let mut abi_version = u32::MAX;
+    for unit in units {
+        abi_version = abi_version.min(validate_wasm(path, &unit.symbol, abi)?);
+    }
+    Ok(abi_version)
+}
+
+/// Checks that a WebAssembly artifact is a valid module exporting `tree_sitter_<symbol>`, built
+/// for an ABI version in the supported range and importing nothing the runtime does not provide.
+/// Returns the ABI version of the language.
+pub fn validate_wasm(path: &Path, symbol: &str, abi: &AbiRange) -> Result<u32, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Validator::new().validate_all(&bytes).map_err(|e| {
+        format!(
+            "{} is not a valid WebAssembly module: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let export = format!("tree_sitter_{}", symbol);
+    let mut imported_functions = 0;
+    let mut imported_globals = 0;
+    let mut memory_base = None;
+    let mut unexpected_imports = Vec::new();
+    let mut language_function = None;
+    let mut function_bodies = Vec::new();
+    let mut data = Vec::new();
+
+    for payload in Parser::new(0).parse_all(&bytes) {
+        match payload.map_err(|e| e.to_string())? {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import.map_err(|e| e.to_string())?;
+                    match import.ty {
+                        TypeRef::Func(_) => imported_functions += 1,
+                        TypeRef::Global(_) => {
+                            if import.name == "__memory_base" {
+                                memory_base = Some(imported_globals);
+                            }
+                            imported_globals += 1;
+                        }
+                        _ => {}
+                    }
+                    if !ALLOWED_IMPORT_MODULES.contains(&import.module)
+                        || !ALLOWED_IMPORTS.contains(&import.name)
+                    {
+                        unexpected_imports.push(format!("{}.{}", import.module, import.name));
+                    }
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for item in reader {
+                    let item = item.map_err(|e| e.to_string())?;
+                    if item.name == export && item.kind == ExternalKind::Func {
+                        language_function = Some(item.index);
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => function_bodies.push(body),
+            Payload::DataSection(reader) => {
+                for segment in reader {
+                    let segment = segment.map_err(|e| e.to_string())?;
+                    if let DataKind::Active { offset_expr, .. } = segment.kind {
+                        let offset =
+                            constant_offset(offset_expr.get_operators_reader(), memory_base);
+                        data.push((offset, segment.data));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !unexpected_imports.is_empty() {
+        return Err(format!(
+            "{} has unexpected imports: {}",
+            path.display(),
+            unexpected_imports.join(", ")
+        ));
+    }
+
+    let language_function = language_function
+        .ok_or_else(|| format!("{} does not export {}", path.display(), export))?;
+    let abi_version = language_function
+        .checked_sub(imported_functions)
+        .and_then(|index| function_bodies.get(index as usize))
+        .and_then(|body| constant_offset(body.get_operators_reader().ok()?, memory_base))
+        .and_then(|address| read_u32(&data, address))
+        .ok_or_else(|| {
+            format!(
+                "Could not determine the ABI version of {} from {}",
+                path.display(),
+                export
+            )
+        })?;
+
+    if !abi.contains(abi_version) {
+        return Err(format!(
+            "{} has ABI version {}, but only versions {} are supported",
+            path.display(),
+            abi_version,
+            abi
+        ));
+    }
+    Ok(abi_version)
+}
+
+/// Evaluates an expression of the form `global.get __memory_base; i32.const N; i32.add`, which
+/// is how side modules address their data, to the offset `N` relative to the memory base.
+/// Returns `None` for any other expression, including ones reading any other global, whose value
+/// is not known.
+fn constant_offset(
+    operators: wasmparser::OperatorsReader,
+    memory_base: Option<u32>,
+) -> Option<u32> {
+    let mut offset = 0u32;
+    for operator in operators {
+        match operator.ok()? {
+            Operator::GlobalGet { global_index } if Some(global_index) == memory_base => {}
+            Operator::I32Add | Operator::End => {}
+            Operator::I32Const { value } => offset = offset.wrapping_add(value as u32),
+            _ => return None,
+        }
+    }
+    Some(offset)
+}
+
+/// Reads the first field of the `TSLanguage` struct at an offset into the data segments, which is
+/// its ABI version.
+fn read_u32(data: &[(Option<u32>, &[u8])], address: u32) -> Option<u32> {
+    data.iter().find_map(|(offset, bytes)| {
+        let start = address.checked_sub((*offset)?)? as usize;
+        let field = bytes.get(start..start + 4)?;
+        Some(u32::from_le_bytes(field.try_into().ok()?))
+    })
+}